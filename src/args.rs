@@ -1,12 +1,71 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 
-#[derive(Parser, Debug)]
+use crate::{
+    config::{config_path, parse_override, ConfigOverride, DEFAULT_CONFIG_PATH},
+    dsp::Preset,
+};
+
+#[derive(Parser, Debug, Clone, Default)]
 #[command(version = "0.1", about, long_about = None)]
 pub struct Args {
     #[arg(short = 'd', long)]
     pub device_ip: Option<String>,
     #[arg(short = 'p', long)]
     pub device_port: Option<u32>,
+    #[arg(long, value_enum)]
+    pub preset: Option<Preset>,
     #[arg(short = 'g', long, action=clap::ArgAction::SetTrue)]
     pub use_gui: bool,
+    /// Check the config file for problems and exit
+    #[arg(long, action=clap::ArgAction::SetTrue)]
+    pub check_config: bool,
+    /// Measure the round trip of a click from the output device back to the input device,
+    /// suggest a latency_offset_ms and exit
+    #[arg(long, action=clap::ArgAction::SetTrue)]
+    pub calibrate_latency: bool,
+    /// Config file to load instead of ~/.config/audio-reactive-led-strip/config.toml
+    #[arg(short = 'c', long)]
+    pub config: Option<PathBuf>,
+    /// Named profile from the config file to apply, e.g. `livingroom` for `[profile.livingroom]`
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// Override any config field, e.g. `--set fps=30`. May be repeated.
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<ConfigOverride>,
+}
+
+impl Args {
+    /// The config file selected by `--config`, or the default one in the user's home directory
+    pub fn config_file_path(&self) -> PathBuf {
+        self.config
+            .clone()
+            .unwrap_or_else(|| config_path(DEFAULT_CONFIG_PATH, true))
+    }
+
+    /// Every config field overridden on the command line, in the order they should be applied
+    pub fn config_overrides(&self) -> Vec<ConfigOverride> {
+        let mut overrides = vec![];
+        if let Some(device_ip) = &self.device_ip {
+            overrides.push(ConfigOverride {
+                key: String::from("device_ip"),
+                value: toml::Value::String(device_ip.clone()),
+            });
+        }
+        if let Some(device_port) = self.device_port {
+            overrides.push(ConfigOverride {
+                key: String::from("device_port"),
+                value: toml::Value::Integer(device_port.into()),
+            });
+        }
+        if let Some(preset) = &self.preset {
+            overrides.push(ConfigOverride {
+                key: String::from("preset"),
+                value: toml::Value::try_from(preset).expect("presets serialize to strings"),
+            });
+        }
+        overrides.extend(self.overrides.iter().cloned());
+        overrides
+    }
 }
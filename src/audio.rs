@@ -1,7 +1,8 @@
 use cpal::{
     default_host,
     traits::{DeviceTrait, HostTrait},
-    InputCallbackInfo, SampleFormat, SampleRate, Stream, StreamError, SupportedStreamConfig,
+    InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, Stream, StreamError,
+    SupportedStreamConfig,
 };
 
 use crate::config::Config;
@@ -29,7 +30,8 @@ where
                 //TODO: for now, mac only supports floating-point sampling formats. In the future,
                 //will want to compile to support i16 and u16 formats as well. Will be a good
                 //case for pattern matching
-                if sample_rates.sample_format() == SampleFormat::F32 && sample_rates.channels() == 1
+                if sample_rates.sample_format() == SampleFormat::F32
+                    && sample_rates.channels() == config.channels
                 {
                     Some(sample_rates)
                 } else {
@@ -40,8 +42,8 @@ where
         .collect();
     if configs.is_empty() {
         panic!(
-            "Could not create the intended audio input config: 1 channel, {}Hz, f32 format",
-            config.mic_rate
+            "Could not create the intended audio input config: {} channel(s), {}Hz, f32 format",
+            config.channels, config.mic_rate
         );
     }
     device
@@ -56,6 +58,46 @@ where
         .expect("Could not build audio stream")
 }
 
+/// Open the default output device at the config's sample rate, preferring the config's number
+/// of channels. `data_callback` fills each buffer, interleaved with the channel count it's given.
+pub fn new_playback_stream<D>(config: &Config, mut data_callback: D) -> Stream
+where
+    D: FnMut(&mut [f32], usize) + Send + 'static,
+{
+    let device = default_host()
+        .default_output_device()
+        .expect("No default output device could be bound");
+    let mut configs: Vec<SupportedStreamConfig> = device
+        .supported_output_configs()
+        .unwrap()
+        .filter(|x| {
+            x.max_sample_rate() >= SampleRate(config.mic_rate)
+                && x.min_sample_rate() <= SampleRate(config.mic_rate)
+                && x.sample_format() == SampleFormat::F32
+        })
+        .map(|x| x.with_sample_rate(SampleRate(config.mic_rate)))
+        .collect();
+    // the input's channels map straight across, anything else is mixed down or spread out
+    configs.sort_by_key(|x| x.channels() != config.channels);
+    let Some(output_config) = configs.first() else {
+        panic!(
+            "Could not create the intended audio output config: {}Hz, f32 format",
+            config.mic_rate
+        );
+    };
+    let channels = output_config.channels() as usize;
+    device
+        .build_output_stream(
+            &output_config.config(),
+            move |data: &mut [f32], _: &OutputCallbackInfo| data_callback(data, channels),
+            |e: StreamError| {
+                println!("Error received from output stream: {}", e);
+            },
+            None,
+        )
+        .expect("Could not build audio output stream")
+}
+
 #[cfg(test)]
 mod test {
     use std::{thread, time::Duration};
@@ -0,0 +1,40 @@
+use audio_reactive_led_strip::{
+    args::Args,
+    calibrate::calibrate_latency,
+    config::{check_config_file, load_config_or_exit, watch_config},
+    Renderer,
+};
+use clap::Parser;
+use std::sync::mpsc;
+
+pub fn main() {
+    let args = Args::parse();
+    let path = args.config_file_path();
+    let overrides = args.config_overrides();
+    if args.check_config {
+        std::process::exit(check_config_file(
+            &path,
+            args.profile.as_deref(),
+            &overrides,
+        ));
+    }
+
+    let config = load_config_or_exit(&path, args.profile.as_deref(), &overrides);
+    if args.calibrate_latency {
+        std::process::exit(calibrate_latency(&config));
+    }
+    let config_updates = watch_config(path, args.profile, overrides);
+
+    let renderer = Renderer::new(config).with_config_updates(config_updates);
+    let (stop_tx, stop_rx) = mpsc::channel();
+
+    ctrlc::set_handler(move || {
+        println!("Ctrl+C received, signaling stop");
+        stop_tx
+            .send(())
+            .expect("stop receiver should be alive until the render loop exits");
+    })
+    .expect("error setting up signal handler");
+
+    renderer.main_loop(stop_rx);
+}
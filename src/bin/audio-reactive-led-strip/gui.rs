@@ -0,0 +1,366 @@
+mod double_slider;
+mod waveform;
+
+use double_slider::DoubleSlider;
+use double_slider::SliderSide;
+use iced::futures::channel::mpsc;
+use iced::widget::{button, column, horizontal_space, pick_list, row, shader, text, text_input};
+use iced::window;
+use iced::Task;
+use iced::{Alignment, Length, Subscription};
+use ndarray::{Array2, Axis};
+use std::path::PathBuf;
+use std::sync;
+use std::thread;
+use waveform::pipeline::Vertex;
+use waveform::Waveform;
+
+use audio_reactive_led_strip::config::save_config;
+use audio_reactive_led_strip::config::watch_config;
+use audio_reactive_led_strip::config::Config;
+use audio_reactive_led_strip::config::ConfigOverride;
+use audio_reactive_led_strip::display_mode::DisplayMode;
+use audio_reactive_led_strip::dsp::GainMode;
+use audio_reactive_led_strip::renderer::GainOverride;
+use audio_reactive_led_strip::renderer::Renderer;
+
+#[derive(Debug, Clone)]
+pub enum GuiMessage {
+    ModeSelected(DisplayMode),
+    SliderUpdated((u32, SliderSide)),
+    PointsUpdated(Vec<Vertex>),
+    TempoUpdated(Option<f64>),
+    ConfigUpdated(Box<Config>),
+    Tick,
+    StopTx(sync::mpsc::Sender<()>),
+    GainTx(sync::mpsc::Sender<GainOverride>),
+    GainModeSelected(GainMode),
+    ManualGainChanged(f64),
+    WindowClose(window::Id),
+    ProfileNameChanged(String),
+    Save,
+    SaveAsProfile,
+}
+
+pub struct Gui {
+    waveform: Waveform,
+    selected_mode: Option<DisplayMode>,
+    left_slider: u32,
+    right_slider: u32,
+    bpm: Option<f64>,
+    gain_mode: GainMode,
+    manual_gain_db: f64,
+    config: Config,
+    config_path: PathBuf,
+    // the profile selected on the command line, which plain saves are written to
+    profile: Option<String>,
+    // the profile name typed in for "save as profile"
+    profile_name: String,
+    update_vertices: Option<Vec<Vertex>>,
+    stop_tx: Option<sync::mpsc::Sender<()>>,
+    gain_tx: Option<sync::mpsc::Sender<GainOverride>>,
+}
+
+impl Gui {
+    fn check_update(&mut self) {
+        if let Some(vertex_updates) = self.update_vertices.take() {
+            self.waveform.update(vertex_updates);
+        }
+    }
+
+    fn update_vertices(&mut self, new_vertices: Vec<Vertex>) {
+        self.update_vertices = Some(new_vertices)
+    }
+
+    fn send_gain(&self) {
+        if let Some(gain_tx) = &self.gain_tx {
+            gain_tx
+                .send(GainOverride {
+                    mode: self.gain_mode,
+                    manual_gain_db: self.manual_gain_db,
+                })
+                .expect("renderer should be alive while the gui is open");
+        }
+    }
+
+    /// Take on a config reloaded by the renderer, keeping the sliders and manual gain within its
+    /// ranges
+    fn update_config(&mut self, config: Config) {
+        if config.n_points != self.config.n_points {
+            self.waveform.resize(config.n_points as usize);
+            // a frame still waiting to be drawn has the old number of points
+            self.update_vertices = None;
+        }
+        let freq_range = |hz: u32| hz.clamp(config.min_freq_hz, config.max_freq_hz);
+        self.left_slider = freq_range(self.left_slider);
+        self.right_slider = freq_range(self.right_slider);
+        let manual_gain_db = self
+            .manual_gain_db
+            .clamp(config.agc_min_gain_db, config.agc_max_gain_db);
+        self.config = config;
+        if manual_gain_db != self.manual_gain_db {
+            self.manual_gain_db = manual_gain_db;
+            self.send_gain();
+        }
+    }
+
+    /// Save the slider range, display mode and gain to the config file, or to the named profile
+    /// in it
+    fn save(&self, profile: Option<&str>) {
+        // only what the gui controls is written, over what the file gives the profile now, so
+        // edits picked up by the config watcher, command line overrides and the keys an existing
+        // profile sets are all left alone
+        let result = save_config(&self.config_path, profile, |config| {
+            config.left_slider_start = self.left_slider;
+            config.right_slider_start = self.right_slider;
+            if let Some(mode) = self.selected_mode {
+                config.display_mode = mode;
+            }
+            config.gain_mode = self.gain_mode;
+            config.manual_gain_db = self.manual_gain_db;
+        });
+        match (result, profile) {
+            (Ok(()), Some(profile)) => println!(
+                "Saved profile {} to {}",
+                profile,
+                self.config_path.display()
+            ),
+            (Ok(()), None) => println!("Saved config to {}", self.config_path.display()),
+            (Err(err), _) => println!("Could not save the config: {}", err),
+        }
+    }
+}
+
+impl Gui {
+    /// Open the gui on `config`, loaded from `config_path` with the named `profile` and the command
+    /// line `overrides`, and start the renderer on the same config. The returned task forwards the
+    /// renderer's updates, including configs reloaded from the file, to the gui.
+    pub fn new(
+        config: Config,
+        config_path: PathBuf,
+        profile: Option<String>,
+        overrides: Vec<ConfigOverride>,
+    ) -> (Self, Task<GuiMessage>) {
+        let config_updates = watch_config(config_path.clone(), profile.clone(), overrides);
+        let renderer = Renderer::new(config.clone()).with_config_updates(config_updates);
+        let (sender, receiver) = mpsc::channel(100);
+        thread::spawn(move || render_to_gui(renderer, sender));
+
+        let mut waveform = Waveform::new();
+        waveform.resize(config.n_points as usize);
+        let gui = Self {
+            waveform,
+            selected_mode: Some(config.display_mode),
+            left_slider: config.left_slider_start,
+            right_slider: config.right_slider_start,
+            bpm: None,
+            gain_mode: config.gain_mode,
+            manual_gain_db: config.manual_gain_db,
+            config,
+            config_path,
+            profile,
+            profile_name: String::new(),
+            update_vertices: None,
+            stop_tx: None,
+            gain_tx: None,
+        };
+        (gui, Task::stream(receiver))
+    }
+
+    pub fn update(&mut self, message: GuiMessage) -> Task<GuiMessage> {
+        match message {
+            GuiMessage::ModeSelected(mode) => {
+                self.selected_mode = Some(mode);
+                Task::none()
+            }
+            GuiMessage::SliderUpdated((value, SliderSide::Left)) => {
+                self.left_slider = value;
+                Task::none()
+            }
+            GuiMessage::SliderUpdated((value, SliderSide::Right)) => {
+                self.right_slider = value;
+                Task::none()
+            }
+            //TODO: figure out wtf is going on here, how can we do renders and vertex updates separately?
+            GuiMessage::PointsUpdated(vertices) => {
+                self.update_vertices(vertices);
+                Task::none()
+            }
+            GuiMessage::TempoUpdated(bpm) => {
+                self.bpm = bpm;
+                Task::none()
+            }
+            GuiMessage::ConfigUpdated(config) => {
+                self.update_config(*config);
+                Task::none()
+            }
+            GuiMessage::Tick => {
+                self.check_update();
+                Task::none()
+            }
+            GuiMessage::StopTx(tx) => {
+                self.stop_tx = Some(tx);
+                Task::none()
+            }
+            GuiMessage::GainTx(tx) => {
+                self.gain_tx = Some(tx);
+                Task::none()
+            }
+            GuiMessage::GainModeSelected(mode) => {
+                self.gain_mode = mode;
+                self.send_gain();
+                Task::none()
+            }
+            GuiMessage::ManualGainChanged(gain_db) => {
+                self.manual_gain_db = gain_db;
+                self.send_gain();
+                Task::none()
+            }
+            GuiMessage::WindowClose(id) => {
+                self.stop_tx
+                    .as_mut()
+                    .expect("stop tx should be initialized by the time window close occurs")
+                    .send(())
+                    .expect("sending the stop signal expected to suceed on normal close");
+                window::close::<GuiMessage>(id)
+            }
+            GuiMessage::ProfileNameChanged(name) => {
+                self.profile_name = name;
+                Task::none()
+            }
+            GuiMessage::Save => {
+                self.save(self.profile.as_deref());
+                Task::none()
+            }
+            GuiMessage::SaveAsProfile => {
+                self.save(Some(self.profile_name.trim()));
+                Task::none()
+            }
+        }
+    }
+
+    pub fn view(&self) -> iced::Element<'_, GuiMessage> {
+        let mode_select = pick_list(
+            &DisplayMode::ALL[..],
+            self.selected_mode,
+            GuiMessage::ModeSelected,
+        );
+
+        let tempo = text(match self.bpm {
+            Some(bpm) => format!("{:.0} BPM", bpm),
+            None => String::from("-- BPM"),
+        })
+        .width(70);
+
+        let slider = DoubleSlider::new(
+            self.config.min_freq_hz..=self.config.max_freq_hz,
+            self.left_slider,
+            self.right_slider,
+            GuiMessage::SliderUpdated,
+        );
+
+        let gain_mode = pick_list(
+            &GainMode::ALL[..],
+            Some(self.gain_mode),
+            GuiMessage::GainModeSelected,
+        );
+        // the manual gain ranges over the limits of the automatic one
+        let manual_gain = iced::widget::slider(
+            self.config.agc_min_gain_db..=self.config.agc_max_gain_db,
+            self.manual_gain_db,
+            GuiMessage::ManualGainChanged,
+        )
+        .step(1.0)
+        .width(120);
+        let manual_gain_label = text(format!("{:.0} dB", self.manual_gain_db)).width(50);
+
+        let profile_name = text_input("profile name", &self.profile_name)
+            .on_input(GuiMessage::ProfileNameChanged)
+            .width(150);
+        let save = button("Save").on_press(GuiMessage::Save);
+        let save_as_profile = button("Save as profile").on_press_maybe(
+            (!self.profile_name.trim().is_empty()).then_some(GuiMessage::SaveAsProfile),
+        );
+
+        let controls_bar = row![
+            horizontal_space().width(30),
+            mode_select,
+            tempo,
+            slider,
+            gain_mode,
+            manual_gain,
+            manual_gain_label,
+            save,
+            profile_name,
+            save_as_profile,
+            horizontal_space().width(30)
+        ]
+        .height(100)
+        .align_y(Alignment::End)
+        .spacing(10);
+
+        let shader = shader(&self.waveform)
+            .width(Length::Fill)
+            .height(Length::Fill);
+
+        let display_and_controls = column![shader, controls_bar].height(600).spacing(10);
+
+        display_and_controls.into()
+    }
+
+    pub fn subscription(&self) -> iced::Subscription<GuiMessage> {
+        Subscription::batch(vec![
+            window::close_requests().map(GuiMessage::WindowClose),
+            window::frames().map(|_| GuiMessage::Tick),
+        ])
+    }
+}
+
+/// Run the renderer, sending each frame and every change of tempo and config to the gui until
+/// the gui sends a stop signal
+fn render_to_gui(renderer: Renderer, mut update_tx: mpsc::Sender<GuiMessage>) {
+    let (stop_tx, stop_rx) = sync::mpsc::channel::<()>();
+    update_tx
+        .try_send(GuiMessage::StopTx(stop_tx))
+        .expect("update tx should be ready to accept messages");
+    let (gain_tx, gain_rx) = sync::mpsc::channel();
+    update_tx
+        .try_send(GuiMessage::GainTx(gain_tx))
+        .expect("update tx should be ready to accept messages");
+
+    let mut shown_bpm = None;
+    let mut shown_config = renderer.config().clone();
+    let _stream = renderer.with_gain_updates(gain_rx).start(move |renderer| {
+        // the config goes first so the gui is ready for frames with a new number of points
+        if renderer.config() != &shown_config {
+            shown_config = renderer.config().clone();
+            update_tx
+                .try_send(GuiMessage::ConfigUpdated(Box::new(shown_config.clone())))
+                .expect("send config update should succeed if channel is open");
+        }
+        update_tx
+            .try_send(GuiMessage::PointsUpdated(send_buffer_to_vertex(
+                renderer.send_buffer(),
+            )))
+            .expect("send points update should succeed if channel is open");
+
+        // only bother the gui when the displayed tempo changes
+        let bpm = renderer.analysis().tempo.bpm.map(f64::round);
+        if bpm != shown_bpm {
+            shown_bpm = bpm;
+            update_tx
+                .try_send(GuiMessage::TempoUpdated(bpm))
+                .expect("send tempo update should succeed if channel is open");
+        }
+    });
+    stop_rx
+        .recv()
+        .expect("stop receiver exists and should not have been closed");
+}
+
+fn send_buffer_to_vertex(send_buffer: &Array2<u8>) -> Vec<Vertex> {
+    send_buffer
+        .axis_iter(Axis(0))
+        .map(|col| Vertex([col[0] as i32, col[1] as i32, col[2] as i32]))
+        .collect::<Vec<Vertex>>()
+}
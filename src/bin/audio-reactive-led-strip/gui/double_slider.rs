@@ -79,8 +79,8 @@ where
     ///   * an inclusive range of possible values
     ///   * the current value of the [`Slider`]
     ///   * a function that will be called when the [`Slider`] is dragged.
-    ///   It receives the new value of the [`Slider`] and must produce a
-    ///   `Message`.
+    ///     It receives the new value of the [`Slider`] and must produce a
+    ///     `Message`.
     pub fn new<F>(range: RangeInclusive<T>, left_value: T, right_value: T, on_change: F) -> Self
     where
         F: 'a + Fn((T, SliderSide)) -> Message,
@@ -310,6 +310,7 @@ where
 
 /// Processes an [`Event`] and updates the [`State`] of a [`Slider`]
 /// accordingly.
+#[allow(clippy::too_many_arguments)]
 pub fn update<Message, T>(
     event: Event,
     layout: Layout<'_>,
@@ -348,10 +349,7 @@ where
             b. if the xpos is less than than the left cursor, then return the left cursor position
         */
 
-        if slider_side.is_none() {
-            return None;
-        }
-        let slider_side = slider_side.unwrap();
+        let slider_side = slider_side?;
         let start: f64 = (*range.start()).into();
         let end: f64 = (*range.end()).into();
 
@@ -384,7 +382,7 @@ where
     };
 
     // TODO: these are used when we implement keyboard use
-    let increment = |value: T| -> Option<T> {
+    let _increment = |value: T| -> Option<T> {
         let step = if state.keyboard_modifiers.shift() {
             shift_step.unwrap_or(step)
         } else {
@@ -402,7 +400,7 @@ where
         T::from_f64(new_value)
     };
 
-    let decrement = |value: T| -> Option<T> {
+    let _decrement = |value: T| -> Option<T> {
         let step = if state.keyboard_modifiers.shift() {
             shift_step.unwrap_or(step)
         } else {
@@ -420,21 +418,24 @@ where
         T::from_f64(new_value)
     };
 
-    let mut change = |new_value: Option<T>, side: SliderSide| match new_value {
-        Some(new_value) => match side {
-            SliderSide::Left if ((*left_value).into() - new_value.into()).abs() > f64::EPSILON => {
-                shell.publish((on_change)((new_value, SliderSide::Left)));
-                *left_value = new_value;
-            }
-            SliderSide::Right
-                if ((*right_value).into() - new_value.into()).abs() > f64::EPSILON =>
-            {
-                shell.publish((on_change)((new_value, SliderSide::Right)));
-                *right_value = new_value;
+    let mut change = |new_value: Option<T>, side: SliderSide| {
+        if let Some(new_value) = new_value {
+            match side {
+                SliderSide::Left
+                    if ((*left_value).into() - new_value.into()).abs() > f64::EPSILON =>
+                {
+                    shell.publish((on_change)((new_value, SliderSide::Left)));
+                    *left_value = new_value;
+                }
+                SliderSide::Right
+                    if ((*right_value).into() - new_value.into()).abs() > f64::EPSILON =>
+                {
+                    shell.publish((on_change)((new_value, SliderSide::Right)));
+                    *right_value = new_value;
+                }
+                _ => (),
             }
-            _ => (),
-        },
-        None => (),
+        }
     };
 
     match event {
@@ -466,28 +467,25 @@ where
         }
         Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
         | Event::Touch(touch::Event::FingerLifted { .. })
-        | Event::Touch(touch::Event::FingerLost { .. }) => {
-            if is_dragging {
-                if let Some(on_release) = on_release.clone() {
-                    shell.publish(on_release);
-                }
-                state.is_dragging = false;
-
-                return event::Status::Captured;
+        | Event::Touch(touch::Event::FingerLost { .. })
+            if is_dragging =>
+        {
+            if let Some(on_release) = on_release.clone() {
+                shell.publish(on_release);
             }
+            state.is_dragging = false;
+
+            return event::Status::Captured;
         }
         Event::Mouse(mouse::Event::CursorMoved { .. })
-        | Event::Touch(touch::Event::FingerMoved { .. }) => {
-            if is_dragging {
-                match state.slider_side {
-                    Some(side) => {
-                        change(cursor.position().and_then(locate), side);
-                    }
-                    None => (),
-                }
-
-                return event::Status::Captured;
+        | Event::Touch(touch::Event::FingerMoved { .. })
+            if is_dragging =>
+        {
+            if let Some(side) = state.slider_side {
+                change(cursor.position().and_then(locate), side);
             }
+
+            return event::Status::Captured;
         }
         // TODO(jhpick): implement increment/decrement for keyboard
         //Event::Keyboard(keyboard::Event::KeyPressed { key, .. }) => {
@@ -515,6 +513,7 @@ where
 }
 
 /// Draws a [`Slider`].
+#[allow(clippy::too_many_arguments)]
 pub fn draw<T, Theme, Renderer>(
     renderer: &mut Renderer,
     layout: Layout<'_>,
@@ -790,13 +789,6 @@ pub trait StyleSheet {
     fn dragging(&self, style: &Self::Style) -> Appearance;
 }
 
-/// The appearance of a slider.
-#[derive(Debug, Clone, Copy)]
-pub struct Style {
-    pub rail_colors: (Color, Color),
-    pub handle: Handle,
-}
-
 /// The style of a slider.
 #[derive(Default)]
 pub enum DoubleSliderStyle {
@@ -7,21 +7,25 @@ use iced::{
 };
 use pipeline::{Pipeline, Vertex};
 
-use crate::config::Config;
+use audio_reactive_led_strip::config::Config;
 
 #[derive(Clone)]
 pub struct Waveform {
-    pub size: f32,
     pub vertices: Vec<Vertex>,
     pub background_color: Color,
 }
 
+impl Default for Waveform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Waveform {
     pub fn new() -> Self {
         let config = Config::default();
 
         let mut scene = Self {
-            size: 0.2,
             vertices: vec![],
             background_color: Color::GREEN,
         };
@@ -49,7 +53,7 @@ impl<Message> shader::Program<Message> for Waveform {
         &self,
         _state: &Self::State,
         _cursor: mouse::Cursor,
-        bounds: Rectangle,
+        _bounds: Rectangle,
     ) -> Self::Primitive {
         Primitive::new(&self.vertices, self.background_color)
     }
@@ -63,9 +67,9 @@ pub struct Primitive {
 }
 
 impl Primitive {
-    pub fn new(vertices: &Vec<Vertex>, background_color: shader::wgpu::Color) -> Self {
+    pub fn new(vertices: &[Vertex], background_color: shader::wgpu::Color) -> Self {
         Self {
-            vertices: vertices.clone(),
+            vertices: vertices.to_vec(),
             background_color,
         }
     }
@@ -79,7 +83,7 @@ impl shader::Primitive for Primitive {
         format: shader::wgpu::TextureFormat,
         storage: &mut shader::Storage,
         bounds: &Rectangle,
-        viewport: &Viewport,
+        _viewport: &Viewport,
     ) {
         if !storage.has::<Pipeline>() {
             storage.store(Pipeline::new(
@@ -11,10 +11,10 @@ pub struct Pipeline {
 impl Pipeline {
     pub fn new(
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
+        _queue: &wgpu::Queue,
         format: wgpu::TextureFormat,
         n_points: u64,
-        size: &iced::Rectangle,
+        _size: &iced::Rectangle,
     ) -> Self {
         //vertices of one cube
         let vertex_buffer = Buffer::new(
@@ -23,16 +23,12 @@ impl Pipeline {
             std::mem::size_of::<Vertex>() as u64,
             wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         );
-        let vertices = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("vertex buffer"),
-            usage: wgpu::BufferUsages::VERTEX,
-            mapped_at_creation: false,
-            size: n_points,
-        });
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("points shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../../../shaders/line.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                include_str!("../../../../../shaders/line.wgsl").into(),
+            ),
         });
 
         let render_pipeline_layout =
@@ -84,30 +80,26 @@ impl Pipeline {
         }
     }
 
-    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: &Vec<Vertex>) {
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: &[Vertex]) {
         //TODO: see if we can resize vertex buffer if cubes amount changed
         // let new_size = num_points * std::mem::size_of::<Vertex>();
         // self.vertices.size(device, new_size as u64);
 
-        let buf_len = (vertices.len() * std::mem::size_of::<Vertex>()) as u64;
+        let buf_len = std::mem::size_of_val(vertices) as u64;
 
         if self.vertex_buffer.raw.size() != buf_len {
             self.vertex_buffer.resize(device, buf_len);
         }
 
         //always write new cube data since they are constantly rotating
-        queue.write_buffer(
-            &self.vertex_buffer.raw,
-            0,
-            bytemuck::cast_slice(vertices.as_slice()),
-        );
+        queue.write_buffer(&self.vertex_buffer.raw, 0, bytemuck::cast_slice(vertices));
     }
 
     pub fn render(
         &self,
         target: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
-        clear_color: wgpu::Color,
+        _clear_color: wgpu::Color,
         viewport: &Rectangle<u32>,
     ) {
         {
@@ -134,10 +126,16 @@ impl Pipeline {
     }
 }
 
+// the contents are only ever read on the gpu side through bytemuck
+#[allow(dead_code)]
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+#[derive(Copy, Clone, Debug)]
 pub struct Vertex(pub [i32; 3]);
 
+// SAFETY: Vertex is a repr(C) wrapper around a plain integer array with no padding
+unsafe impl bytemuck::Zeroable for Vertex {}
+unsafe impl bytemuck::Pod for Vertex {}
+
 impl Vertex {
     const ATTRS: [wgpu::VertexAttribute; 2] =
         wgpu::vertex_attr_array![0 => Float32x2, 1 => Sint32x2];
@@ -0,0 +1,32 @@
+mod gui;
+
+use audio_reactive_led_strip::{
+    args::Args,
+    calibrate::calibrate_latency,
+    config::{check_config_file, load_config_or_exit},
+};
+use clap::Parser;
+use gui::Gui;
+
+pub fn main() -> iced::Result {
+    let args = Args::parse();
+    let path = args.config_file_path();
+    let overrides = args.config_overrides();
+    if args.check_config {
+        std::process::exit(check_config_file(
+            &path,
+            args.profile.as_deref(),
+            &overrides,
+        ));
+    }
+
+    // loaded once here, the gui and the renderer both start from this config
+    let config = load_config_or_exit(&path, args.profile.as_deref(), &overrides);
+    if args.calibrate_latency {
+        std::process::exit(calibrate_latency(&config));
+    }
+    iced::application("Audio Reactive Renderer", Gui::update, Gui::view)
+        .subscription(Gui::subscription)
+        .exit_on_close_request(false)
+        .run_with(move || Gui::new(config, path, args.profile, overrides))
+}
@@ -0,0 +1,229 @@
+//! Measures how long a click takes from the default output device back to the default input
+//! device, e.g. through a loopback device, to suggest a `latency_offset_ms`.
+
+use std::{
+    f64::consts::PI,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use cpal::{traits::StreamTrait, InputCallbackInfo};
+
+use crate::{
+    audio::{new_audio_stream, new_playback_stream},
+    config::Config,
+};
+
+/// Clicks played, the median of their round trips is taken so that a few missed or
+/// mistimed ones don't matter
+const CLICKS: usize = 8;
+/// Time between clicks, which is also the longest round trip that can be measured
+const CLICK_INTERVAL: Duration = Duration::from_millis(500);
+const CLICK_LENGTH: Duration = Duration::from_millis(5);
+const CLICK_HZ: f64 = 2000.0;
+const CLICK_LEVEL: f64 = 0.5;
+/// Quietest peak, relative to full scale, that counts as hearing a click
+const MIN_CLICK_LEVEL: f32 = 0.01;
+
+/// The round trips of the clicks that were heard
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub round_trips: Vec<Duration>,
+}
+
+impl Calibration {
+    /// The median round trip, if any click was heard
+    pub fn round_trip(&self) -> Option<Duration> {
+        let mut round_trips = self.round_trips.clone();
+        round_trips.sort();
+        round_trips.get(round_trips.len() / 2).copied()
+    }
+
+    /// The `latency_offset_ms` that lines the lights up with audio played back by the renderer.
+    /// The round trip is taken to split evenly between the output and the input, and the lights
+    /// to follow the captured audio by about a frame, half waiting for the hop to fill and half
+    /// for the output to tick.
+    pub fn suggested_offset_ms(&self, config: &Config) -> Option<i32> {
+        let output_ms = self.round_trip()?.as_secs_f64() * 1000. / 2.;
+        let frame_ms = 1000. / config.fps as f64;
+        Some((output_ms - frame_ms).round() as i32)
+    }
+}
+
+/// Play clicks on the default output device while capturing the default input device, and
+/// time how long each takes to come back. The input has to hear the output, through a loopback
+/// device or a microphone next to the speakers.
+pub fn measure_round_trips(config: &Config) -> Calibration {
+    let rate = config.mic_rate as f64;
+    let start = Instant::now();
+    let interval = (CLICK_INTERVAL.as_secs_f64() * rate) as usize;
+    let length = (CLICK_LENGTH.as_secs_f64() * rate) as usize;
+
+    // when each click was handed to the output, in seconds since the start
+    let (played_tx, played_rx) = mpsc::channel();
+    let mut written = 0;
+    let playback = new_playback_stream(config, move |out, channels| {
+        let now = start.elapsed().as_secs_f64();
+        for (i, frame) in out.chunks_mut(channels).enumerate() {
+            // the first click waits an interval for both streams to settle
+            let click = written / interval;
+            let phase = written % interval;
+            let sample = if (1..=CLICKS).contains(&click) && phase < length {
+                if phase == 0 {
+                    // the receiver only goes away once the clicks are over
+                    let _ = played_tx.send(now + i as f64 / rate);
+                }
+                CLICK_LEVEL * (2. * PI * CLICK_HZ * phase as f64 / rate).sin()
+            } else {
+                0.0
+            };
+            frame.fill(sample as f32);
+            written += 1;
+        }
+    });
+
+    // the captured audio mixed down to mono, along with when its first frame was captured
+    let (captured_tx, captured_rx) = mpsc::channel();
+    let channels = config.channels as usize;
+    let capture = new_audio_stream(
+        config.clone(),
+        move |audio_data: &[f32], _: &InputCallbackInfo| {
+            let mono: Vec<f32> = audio_data
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .collect();
+            let first = start.elapsed().as_secs_f64() - mono.len() as f64 / rate;
+            let _ = captured_tx.send((first, mono));
+        },
+    );
+
+    capture.play().expect("error playing audio stream");
+    playback.play().expect("error playing audio output stream");
+    thread::sleep(CLICK_INTERVAL * (CLICKS as u32 + 2));
+    drop(playback);
+    drop(capture);
+
+    let played: Vec<f64> = played_rx.try_iter().collect();
+    let captured: Vec<(f64, Vec<f32>)> = captured_rx.try_iter().collect();
+    Calibration {
+        round_trips: find_round_trips(&played, &captured, rate, CLICK_INTERVAL.as_secs_f64())
+            .into_iter()
+            .map(Duration::from_secs_f64)
+            .collect(),
+    }
+}
+
+/// Find each click played at the times in `played` in the `captured` chunks of audio, taking
+/// the first sample that reaches half the loudest one within `interval` seconds after the click
+/// as its arrival. Returns the round trip of each click that was heard, in seconds.
+fn find_round_trips(
+    played: &[f64],
+    captured: &[(f64, Vec<f32>)],
+    rate: f64,
+    interval: f64,
+) -> Vec<f64> {
+    played
+        .iter()
+        .filter_map(|&click| {
+            let heard: Vec<(f64, f32)> = captured
+                .iter()
+                .flat_map(|(first, samples)| {
+                    samples
+                        .iter()
+                        .enumerate()
+                        .map(move |(i, sample)| (first + i as f64 / rate, sample.abs()))
+                })
+                .filter(|(time, _)| *time >= click && *time < click + interval)
+                .collect();
+            let peak = heard.iter().map(|(_, level)| *level).fold(0.0, f32::max);
+            if peak < MIN_CLICK_LEVEL {
+                return None;
+            }
+            heard
+                .iter()
+                .find(|(_, level)| *level >= peak / 2.)
+                .map(|(time, _)| time - click)
+        })
+        .collect()
+}
+
+/// Measure the round trip of a click and print it along with the suggested latency offset.
+/// Returns the process exit code.
+pub fn calibrate_latency(config: &Config) -> i32 {
+    println!(
+        "playing {} clicks on the default output device, the default input device has to hear \
+         them, e.g. through a loopback device",
+        CLICKS
+    );
+    let calibration = measure_round_trips(config);
+    let (Some(round_trip), Some(offset)) = (
+        calibration.round_trip(),
+        calibration.suggested_offset_ms(config),
+    ) else {
+        println!("no clicks were heard, check that the input is connected to the output");
+        return 1;
+    };
+    println!(
+        "heard {} of {} clicks, round trip {:.1} ms",
+        calibration.round_trips.len(),
+        CLICKS,
+        round_trip.as_secs_f64() * 1000.
+    );
+    println!("suggested latency_offset_ms = {}", offset);
+    if offset < 0 && !config.playback {
+        println!(
+            "a negative offset holds back the audio the renderer plays, so needs playback = true"
+        );
+    }
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_round_trips() {
+        let rate = 1000.0;
+        // a click every second, heard 120 ms later over a low noise floor, except the last
+        let mut audio = vec![0.001; 3000];
+        for click in [0, 1000] {
+            audio[click + 120..click + 125].copy_from_slice(&[0.2, -0.4, 0.3, -0.1, 0.05]);
+        }
+        let captured: Vec<(f64, Vec<f32>)> = audio
+            .chunks(64)
+            .enumerate()
+            .map(|(i, chunk)| (i as f64 * 64. / rate, chunk.to_vec()))
+            .collect();
+
+        let round_trips = find_round_trips(&[0.0, 1.0, 2.0], &captured, rate, 0.5);
+        assert_eq!(round_trips.len(), 2);
+        for round_trip in round_trips {
+            assert!((round_trip - 0.12).abs() < 1e-9, "{round_trip}");
+        }
+    }
+
+    #[test]
+    fn test_suggested_offset() {
+        let config = Config {
+            fps: 50,
+            ..Default::default()
+        };
+        let ms = Duration::from_millis;
+        let calibration = Calibration {
+            round_trips: vec![ms(30), ms(200), ms(20), ms(25), ms(22)],
+        };
+        assert_eq!(calibration.round_trip(), Some(ms(25)));
+        // the audio comes out 12.5 ms after capture, ahead of the lights a frame of 20 ms later
+        assert_eq!(calibration.suggested_offset_ms(&config), Some(-8));
+        let calibration = Calibration {
+            round_trips: vec![ms(100)],
+        };
+        assert_eq!(calibration.suggested_offset_ms(&config), Some(30));
+        let calibration = Calibration {
+            round_trips: vec![],
+        };
+        assert_eq!(calibration.suggested_offset_ms(&config), None);
+    }
+}
@@ -1,31 +1,119 @@
 use dirs::home_dir;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::File,
-    io::Read,
+    collections::HashMap,
+    fmt::Display,
+    fs, io,
+    net::SocketAddr,
     path::{Path, PathBuf},
+    process,
+    sync::mpsc,
+    thread,
+    time::Duration,
 };
+use toml_edit::{ImDocument, TableLike};
 
-use crate::args::Args;
+use crate::{
+    display_mode::DisplayMode,
+    dsp::{EqBand, FrequencyScale, GainMode, Preset, Window},
+};
 
 pub static DEFAULT_CONFIG_PATH: &str = ".config/audio-reactive-led-strip/config.toml";
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
-#[serde(default)]
+/// The table holding named profiles, e.g. `[profile.livingroom]`
+const PROFILE_KEY: &str = "profile";
+/// How often the config watcher checks the config file for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long a changed config file has to stay unchanged before it is parsed
+const CONFIG_SETTLE_TIME: Duration = Duration::from_millis(50);
+/// Flash rate at and above which flashing can trigger photosensitive seizures, so
+/// max_flash_hz has to stay below it
+const MAX_SAFE_FLASH_HZ: f64 = 3.0;
+/// Longest latency_offset_ms either way, which bounds the audio the playback delay holds
+pub const MAX_LATENCY_OFFSET_MS: i32 = 2000;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Invalid(Vec<ConfigProblem>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read config: {}", err),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid config:")?;
+                for problem in problems {
+                    write!(f, "\n    {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A single problem found in a config source, located by line where possible
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigProblem {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub device_ip: String,
     pub device_port: u32,
     pub software_gamma_correction: bool,
     pub n_points: u8,
     pub mic_rate: u32,
+    pub channels: u16,
     pub fps: u32,
     pub min_freq_hz: u32,
     pub max_freq_hz: u32,
     pub n_fft_bins: u32,
+    pub highpass_hz: f64,
+    pub pre_emphasis: f64,
+    pub eq: Vec<EqBand>,
+    pub window: Window,
     pub n_mel_bands: u32,
+    pub frequency_scale: FrequencyScale,
     pub min_volume_threshold: f64,
+    pub gate_release_ms: u32,
+    pub latency_offset_ms: i32,
+    pub playback: bool,
+    pub max_flash_hz: f64,
+    pub gain_mode: GainMode,
+    pub agc_target: f64,
+    pub agc_min_gain_db: f64,
+    pub agc_max_gain_db: f64,
+    pub manual_gain_db: f64,
+    pub mel_gain_time: TimeConstant,
+    pub mel_smoothing_time: TimeConstant,
+    pub scroll_gain_time: TimeConstant,
+    pub power_gain_time: TimeConstant,
+    pub power_pixels_time: TimeConstant,
+    pub spectrum_common_mode_time: TimeConstant,
+    pub spectrum_red_time: TimeConstant,
+    pub spectrum_blue_time: TimeConstant,
+    pub chroma_time: TimeConstant,
+    pub vu_peak_time: TimeConstant,
+    pub strobe_time: TimeConstant,
+    pub bars_peak_time: TimeConstant,
     pub left_slider_start: u32,
     pub right_slider_start: u32,
+    pub preset: Preset,
+    pub display_mode: DisplayMode,
 }
 
 impl Default for Config {
@@ -36,88 +124,984 @@ impl Default for Config {
             software_gamma_correction: true,
             n_points: 255,
             mic_rate: 44100,
+            channels: 1,
             fps: 60,
             min_freq_hz: 200,
             max_freq_hz: 12000,
             n_fft_bins: 2048,
+            highpass_hz: 0.0,
+            pre_emphasis: 0.0,
+            eq: Vec::new(),
+            window: Window::Hann,
             n_mel_bands: 24,
+            frequency_scale: FrequencyScale::Mel,
             min_volume_threshold: 1e-7,
+            gate_release_ms: 500,
+            latency_offset_ms: 0,
+            playback: false,
+            max_flash_hz: 2.0,
+            gain_mode: GainMode::Auto,
+            agc_target: 1.0,
+            agc_min_gain_db: -80.0,
+            agc_max_gain_db: 20.0,
+            manual_gain_db: -40.0,
+            // these match the per frame alphas the effects were originally tuned with at 60 fps
+            mel_gain_time: TimeConstant::new(1658.0, 3.6),
+            mel_smoothing_time: TimeConstant::new(24.0, 3.6),
+            scroll_gain_time: TimeConstant::new(75.0, 75.0),
+            power_gain_time: TimeConstant::new(75.0, 75.0),
+            power_pixels_time: TimeConstant::new(3.6, 158.0),
+            spectrum_common_mode_time: TimeConstant::new(3.6, 1658.0),
+            spectrum_red_time: TimeConstant::new(75.0, 3.6),
+            spectrum_blue_time: TimeConstant::new(158.0, 24.0),
+            chroma_time: TimeConstant::new(150.0, 500.0),
+            vu_peak_time: TimeConstant::new(0.0, 1500.0),
+            strobe_time: TimeConstant::new(0.0, 60.0),
+            bars_peak_time: TimeConstant::new(0.0, 1000.0),
             left_slider_start: 200,
             right_slider_start: 20000,
+            preset: Preset::Scroll,
+            display_mode: DisplayMode::Frequency,
         }
     }
 }
 
 impl Config {
-    pub fn merge_with_args(&mut self, args: Args) {
-        if let Some(device_ip) = args.device_ip {
-            self.device_ip = device_ip
+    /// Check the values of every field against the ranges the renderer can work with, returning
+    /// the offending key and a description for each violation
+    pub fn validate(&self) -> Vec<(&'static str, String)> {
+        let mut problems = vec![];
+        if format!("{}:{}", self.device_ip, self.device_port)
+            .parse::<SocketAddr>()
+            .is_err()
+        {
+            problems.push((
+                "device_ip",
+                format!(
+                    "`{}:{}` is not a valid ip address and port",
+                    self.device_ip, self.device_port
+                ),
+            ));
+        }
+        if self.n_points < 2 {
+            problems.push(("n_points", String::from("must be at least 2")));
+        }
+        if self.mic_rate == 0 {
+            problems.push(("mic_rate", String::from("must be greater than 0")));
+        }
+        if !(1..=2).contains(&self.channels) {
+            problems.push((
+                "channels",
+                format!("must be 1 for mono or 2 for stereo, got {}", self.channels),
+            ));
+        }
+        if self.fps == 0 {
+            problems.push(("fps", String::from("must be greater than 0")));
+        } else if self.fps > self.mic_rate {
+            problems.push((
+                "fps",
+                format!(
+                    "must not be greater than mic_rate, as every frame needs at least one sample \
+                     ({} > {})",
+                    self.fps, self.mic_rate
+                ),
+            ));
+        }
+        if self.min_freq_hz >= self.max_freq_hz {
+            problems.push((
+                "min_freq_hz",
+                format!(
+                    "must be less than max_freq_hz ({} >= {})",
+                    self.min_freq_hz, self.max_freq_hz
+                ),
+            ));
+        }
+        if self.max_freq_hz >= self.mic_rate / 2 {
+            problems.push((
+                "max_freq_hz",
+                format!(
+                    "must be below the nyquist frequency of mic_rate / 2 ({} >= {})",
+                    self.max_freq_hz,
+                    self.mic_rate / 2
+                ),
+            ));
+        }
+        if !(self.highpass_hz >= 0.0 && self.highpass_hz < self.mic_rate as f64 / 2.0) {
+            problems.push((
+                "highpass_hz",
+                format!(
+                    "must be 0 to turn it off, or below the nyquist frequency of mic_rate / 2, \
+                     got {}",
+                    self.highpass_hz
+                ),
+            ));
+        }
+        if !(0.0..1.0).contains(&self.pre_emphasis) {
+            problems.push((
+                "pre_emphasis",
+                format!(
+                    "must be 0 to turn it off, or a coefficient below 1, got {}",
+                    self.pre_emphasis
+                ),
+            ));
+        }
+        for (i, band) in self.eq.iter().enumerate() {
+            if let Some(problem) = band.check(self.mic_rate) {
+                problems.push(("eq", format!("band {}: {}", i + 1, problem)));
+            }
+        }
+        if !self.n_fft_bins.is_power_of_two() {
+            problems.push((
+                "n_fft_bins",
+                format!("must be a power of two, got {}", self.n_fft_bins),
+            ));
         }
-        if let Some(device_port) = args.device_port {
-            self.device_port = device_port
+        if self.n_mel_bands < 3 {
+            problems.push(("n_mel_bands", String::from("must be at least 3")));
+        } else if self.n_mel_bands > self.n_fft_bins / 2 {
+            problems.push((
+                "n_mel_bands",
+                format!(
+                    "must be at most n_fft_bins / 2 ({} > {})",
+                    self.n_mel_bands,
+                    self.n_fft_bins / 2
+                ),
+            ));
+        }
+        if self.min_freq_hz < self.max_freq_hz && self.n_mel_bands >= 3 {
+            if let Some(problem) = self.frequency_scale.check_range(
+                self.min_freq_hz as f64,
+                self.max_freq_hz as f64,
+                self.n_mel_bands as usize,
+            ) {
+                problems.push(("frequency_scale", problem));
+            }
+        }
+        if self.min_volume_threshold.is_nan() || self.min_volume_threshold < 0.0 {
+            problems.push((
+                "min_volume_threshold",
+                String::from("must be a non-negative number"),
+            ));
+        }
+        if self.latency_offset_ms.abs() > MAX_LATENCY_OFFSET_MS {
+            problems.push((
+                "latency_offset_ms",
+                format!(
+                    "must be between -{MAX_LATENCY_OFFSET_MS} and {MAX_LATENCY_OFFSET_MS}, got {}",
+                    self.latency_offset_ms
+                ),
+            ));
+        }
+        if self.latency_offset_ms < 0 && !self.playback {
+            problems.push((
+                "latency_offset_ms",
+                String::from(
+                    "can only be negative with playback = true, as it holds back the audio the \
+                     renderer plays",
+                ),
+            ));
+        }
+        if !(self.max_flash_hz > 0.0 && self.max_flash_hz < MAX_SAFE_FLASH_HZ) {
+            problems.push((
+                "max_flash_hz",
+                format!(
+                    "must be above 0 and below {MAX_SAFE_FLASH_HZ}, flashing any faster can \
+                     trigger seizures in photosensitive viewers, got {}",
+                    self.max_flash_hz
+                ),
+            ));
+        }
+        if !(self.agc_target > 0.0 && self.agc_target.is_finite()) {
+            problems.push(("agc_target", String::from("must be a positive number")));
+        }
+        for (key, db) in [
+            ("agc_min_gain_db", self.agc_min_gain_db),
+            ("agc_max_gain_db", self.agc_max_gain_db),
+            ("manual_gain_db", self.manual_gain_db),
+        ] {
+            if !db.is_finite() {
+                problems.push((key, String::from("must be a finite number")));
+            }
+        }
+        if self.agc_min_gain_db > self.agc_max_gain_db {
+            problems.push((
+                "agc_min_gain_db",
+                format!(
+                    "must not be greater than agc_max_gain_db ({} > {})",
+                    self.agc_min_gain_db, self.agc_max_gain_db
+                ),
+            ));
+        }
+        for (key, time) in self.time_constants() {
+            if !time.is_valid() {
+                problems.push((
+                    key,
+                    String::from("attack_ms and release_ms must be non-negative numbers"),
+                ));
+            }
+        }
+        if self.left_slider_start > self.right_slider_start {
+            problems.push((
+                "left_slider_start",
+                String::from("must not be greater than right_slider_start"),
+            ));
+        }
+        problems
+    }
+
+    /// Every smoothing time constant along with its key
+    pub fn time_constants(&self) -> [(&'static str, TimeConstant); 12] {
+        [
+            ("mel_gain_time", self.mel_gain_time),
+            ("mel_smoothing_time", self.mel_smoothing_time),
+            ("scroll_gain_time", self.scroll_gain_time),
+            ("power_gain_time", self.power_gain_time),
+            ("power_pixels_time", self.power_pixels_time),
+            ("spectrum_common_mode_time", self.spectrum_common_mode_time),
+            ("spectrum_red_time", self.spectrum_red_time),
+            ("spectrum_blue_time", self.spectrum_blue_time),
+            ("chroma_time", self.chroma_time),
+            ("vu_peak_time", self.vu_peak_time),
+            ("strobe_time", self.strobe_time),
+            ("bars_peak_time", self.bars_peak_time),
+        ]
+    }
+
+    /// Whether the mel filter bank needs to be rebuilt to move from this config to `other`
+    pub fn mel_bank_changed(&self, other: &Config) -> bool {
+        self.mic_rate != other.mic_rate
+            || self.n_fft_bins != other.n_fft_bins
+            || self.n_mel_bands != other.n_mel_bands
+            || self.frequency_scale != other.frequency_scale
+            || self.min_freq_hz != other.min_freq_hz
+            || self.max_freq_hz != other.max_freq_hz
+    }
+
+    /// Whether the time domain filters on the input need to be rebuilt to move from this config to
+    /// `other`
+    pub fn input_filter_changed(&self, other: &Config) -> bool {
+        self.mic_rate != other.mic_rate
+            || self.highpass_hz != other.highpass_hz
+            || self.pre_emphasis != other.pre_emphasis
+            || self.eq != other.eq
+    }
+
+    /// Whether the led connection needs to be reopened to move from this config to `other`
+    pub fn output_changed(&self, other: &Config) -> bool {
+        self.device_ip != other.device_ip
+            || self.device_port != other.device_port
+            || self.software_gamma_correction != other.software_gamma_correction
+    }
+}
+
+/// Attack and release times of a smoothing filter, e.g. `{ attack_ms = 75, release_ms = 4 }`.
+/// Unlike per frame coefficients these feel the same at any frame rate.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct TimeConstant {
+    /// Time for a rising value to cover 63% of the step, in milliseconds
+    pub attack_ms: f64,
+    /// Time for a falling value to cover 63% of the step, in milliseconds
+    pub release_ms: f64,
+}
+
+impl TimeConstant {
+    pub const fn new(attack_ms: f64, release_ms: f64) -> Self {
+        Self {
+            attack_ms,
+            release_ms,
         }
     }
+
+    /// The per frame (rise, decay) coefficients of an exponential filter updated at `fps`
+    pub fn alphas(&self, fps: u32) -> (f64, f64) {
+        let alpha = |ms: f64| {
+            if ms <= 0.0 {
+                1.0
+            } else {
+                1.0 - (-1000.0 / (ms * fps as f64)).exp()
+            }
+        };
+        (alpha(self.attack_ms), alpha(self.release_ms))
+    }
+
+    fn is_valid(&self) -> bool {
+        [self.attack_ms, self.release_ms]
+            .iter()
+            .all(|ms| ms.is_finite() && *ms >= 0.0)
+    }
+}
+
+/// A `key=value` override for a single config field, given on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigOverride {
+    pub key: String,
+    pub value: toml::Value,
+}
+
+/// Parse a `key=value` override. The value is read as a toml value where possible, so `fps=30` is
+/// an integer, and as a plain string otherwise, so `device_ip=10.0.0.2` needs no quoting.
+pub fn parse_override(arg: &str) -> Result<ConfigOverride, String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got `{}`", arg))?;
+    let key = key.trim().to_string();
+    let value = value.trim();
+    let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    Ok(ConfigOverride { key, value })
 }
 
-pub fn load_config(path_str: &String, use_home_dir: bool) -> Config {
-    let mut path = Path::new(path_str);
-    let mut path_buf: PathBuf;
+/// Resolve a config path, optionally relative to the user's home directory
+pub fn config_path(path_str: &str, use_home_dir: bool) -> PathBuf {
     if use_home_dir {
-        path_buf = home_dir().unwrap();
-        path_buf.push(path);
-        path = path_buf.as_path();
+        let mut path_buf = home_dir().unwrap();
+        path_buf.push(path_str);
+        path_buf
+    } else {
+        PathBuf::from(path_str)
     }
-    let mut source = String::new();
+}
 
-    let read_file = match File::open(path) {
-        Err(_) => {
-            println!(
-                "Could not open path {}, loading default config",
-                path.display()
-            );
-            return Config::default();
+/// Read and parse a config file with the named `profile` and the command line `overrides`,
+/// surfacing any error to the caller
+pub fn read_config(
+    path: &Path,
+    profile: Option<&str>,
+    overrides: &[ConfigOverride],
+) -> Result<Config, ConfigError> {
+    let source = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    parse_config(&source, profile, overrides)
+}
+
+/// Where the value of a key came from, so problems can point back at it
+#[derive(Clone, Copy)]
+enum ValueOrigin {
+    File(Option<usize>),
+    CommandLine,
+}
+
+impl ValueOrigin {
+    fn problem(self, message: String) -> ConfigProblem {
+        match self {
+            ValueOrigin::File(line) => ConfigProblem { line, message },
+            ValueOrigin::CommandLine => ConfigProblem {
+                line: None,
+                message: format!("command line: {}", message),
+            },
         }
-        Ok(mut file) => file.read_to_string(&mut source),
+    }
+}
+
+/// Parse and validate a config source, layering the named `profile` over the top level keys and
+/// the command line `overrides` over both. Unknown keys, values of the wrong type and values out
+/// of range are all collected so that every problem can be reported at once.
+pub fn parse_config(
+    source: &str,
+    profile: Option<&str>,
+    overrides: &[ConfigOverride],
+) -> Result<Config, ConfigError> {
+    // toml_edit keeps the spans of every key, which lets us point at the offending lines
+    let document = ImDocument::parse(source).map_err(|err| {
+        ConfigError::Invalid(vec![ConfigProblem {
+            line: err.span().map(|span| line_number(source, span.start)),
+            message: err.message().to_string(),
+        }])
+    })?;
+    let key_line = |table: Option<&dyn TableLike>, key: &str| {
+        table
+            .and_then(|table| table.get_key_value(key))
+            .and_then(|(key, _)| key.span())
+            .map(|span| line_number(source, span.start))
     };
+    let mut table: toml::Table = toml::from_str(source).expect("source already parsed as toml");
 
-    if let Err(e) = read_file {
-        println!(
-            "Could read config due to an error: {:?}. Loading default config instead",
-            e
-        );
-        return Config::default();
+    let mut problems = vec![];
+    let mut layered = toml::Table::new();
+    let mut origins = HashMap::new();
+    let mut set = |key: String, value, origin| {
+        origins.insert(key.clone(), origin);
+        layered.insert(key, value);
+    };
+
+    let profiles = table.remove(PROFILE_KEY);
+    for (key, value) in table {
+        let origin = ValueOrigin::File(key_line(Some(document.as_table()), &key));
+        set(key, value, origin);
     }
 
-    let parsed_toml = toml::from_str::<Config>(source.as_str());
-    match parsed_toml {
-        Ok(config) => config,
+    let profiles_document = document
+        .get(PROFILE_KEY)
+        .and_then(|item| item.as_table_like());
+    match profiles {
+        Some(toml::Value::Table(profiles)) => {
+            for (name, keys) in profiles {
+                let profile_document = profiles_document
+                    .and_then(|profiles| profiles.get(&name))
+                    .and_then(|item| item.as_table_like());
+                let toml::Value::Table(keys) = keys else {
+                    problems.push(ConfigProblem {
+                        line: key_line(profiles_document, &name),
+                        message: format!("profile `{}` must be a table", name),
+                    });
+                    continue;
+                };
+                let selected = profile == Some(name.as_str());
+                for (key, value) in keys {
+                    let origin = ValueOrigin::File(key_line(profile_document, &key));
+                    if selected {
+                        set(key, value, origin);
+                    } else if let Some(message) = check_key(&key, value) {
+                        // profiles that aren't in use are still checked so mistakes show up early
+                        problems.push(origin.problem(format!("profile `{}`: {}", name, message)));
+                    }
+                }
+            }
+        }
+        Some(_) => problems.push(ConfigProblem {
+            line: key_line(Some(document.as_table()), PROFILE_KEY),
+            message: format!("`{}` must be a table of profiles", PROFILE_KEY),
+        }),
+        None => {}
+    }
+    if let Some(name) = profile {
+        if !profiles_document.is_some_and(|profiles| profiles.contains_key(name)) {
+            problems.push(ConfigProblem {
+                line: None,
+                message: format!("no profile named `{}`", name),
+            });
+        }
+    }
+
+    for ConfigOverride { key, value } in overrides.iter().cloned() {
+        set(key, value, ValueOrigin::CommandLine);
+    }
+
+    let mut valid = toml::Table::new();
+    for (key, value) in layered {
+        match check_key(&key, value.clone()) {
+            Some(message) => problems.push(origins[&key].problem(message)),
+            None => {
+                valid.insert(key, value);
+            }
+        }
+    }
+
+    let config = Config::deserialize(valid).expect("every remaining key deserialized on its own");
+    problems.extend(config.validate().into_iter().map(|(key, message)| {
+        let origin = origins.get(key).copied().unwrap_or(ValueOrigin::File(None));
+        origin.problem(format!("`{}` {}", key, message))
+    }));
+
+    if problems.is_empty() {
+        Ok(config)
+    } else {
+        problems.sort_by_key(|problem| problem.line);
+        Err(ConfigError::Invalid(problems))
+    }
+}
+
+/// Check that `key` is a config field and that `value` has the right type for it, describing the
+/// problem if not
+fn check_key(key: &str, value: toml::Value) -> Option<String> {
+    let known_keys =
+        toml::Table::try_from(Config::default()).expect("config serializes to a table");
+    if !known_keys.contains_key(key) {
+        return Some(format!("unknown key `{}`", key));
+    }
+    // deserialize keys one at a time so one bad value doesn't hide the others
+    Config::deserialize(toml::Table::from_iter([(key.to_string(), value)]))
+        .err()
+        .map(|err| format!("`{}`: {}", key, err.message()))
+}
+
+/// One-based line number of a byte offset into `source`
+fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+/// Check the config file at `path` with the named `profile` and the command line `overrides`,
+/// printing every problem found. Returns the process exit code.
+pub fn check_config_file(path: &Path, profile: Option<&str>, overrides: &[ConfigOverride]) -> i32 {
+    match read_config(path, profile, overrides) {
+        Ok(_) => {
+            println!("{}: ok", path.display());
+            0
+        }
+        Err(ConfigError::Invalid(problems)) => {
+            for problem in problems {
+                match problem.line {
+                    Some(line) => println!("{}:{}: {}", path.display(), line, problem.message),
+                    None => println!("{}: {}", path.display(), problem.message),
+                }
+            }
+            1
+        }
         Err(err) => {
+            println!("{}: {}", path.display(), err);
+            1
+        }
+    }
+}
+
+/// Spawn a thread that polls the config file at `path` and sends every successfully parsed
+/// revision, with the named `profile` and the command line `overrides`, to the returned receiver.
+/// Revisions that fail to parse are reported and skipped so that the running config is kept. The
+/// thread exits once the receiver is dropped.
+pub fn watch_config(
+    path: PathBuf,
+    profile: Option<String>,
+    overrides: Vec<ConfigOverride>,
+) -> mpsc::Receiver<Config> {
+    let (tx, rx) = mpsc::channel();
+    let mut last_source = fs::read_to_string(&path).ok();
+    thread::spawn(move || loop {
+        thread::sleep(CONFIG_POLL_INTERVAL);
+        let source = fs::read_to_string(&path).ok();
+        if source == last_source {
+            continue;
+        }
+        // editors often truncate the file before writing it, which would parse as an empty
+        // config, so wait for the contents to settle first
+        thread::sleep(CONFIG_SETTLE_TIME);
+        if fs::read_to_string(&path).ok() != source {
+            continue;
+        }
+        last_source = source;
+
+        let Some(source) = &last_source else {
+            println!(
+                "Config file {} was removed, keeping the current config",
+                path.display()
+            );
+            continue;
+        };
+        match parse_config(source, profile.as_deref(), &overrides) {
+            Ok(config) => {
+                if tx.send(config).is_err() {
+                    return;
+                }
+            }
+            Err(err) => println!("{}\nKeeping the current config", err),
+        }
+    });
+    rx
+}
+
+/// Load the config at `path` with the named `profile` and the command line `overrides`. A missing
+/// file falls back to the default config, but a file that exists and is invalid is an error.
+pub fn load_config(
+    path: &Path,
+    profile: Option<&str>,
+    overrides: &[ConfigOverride],
+) -> Result<Config, ConfigError> {
+    match read_config(path, profile, overrides) {
+        Err(ConfigError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
             println!(
-                "Error parsing config toml: {:?}. Loading default config instead",
-                err
+                "Could not open path {}, loading default config",
+                path.display()
             );
-            Config::default()
+            parse_config("", profile, overrides)
         }
+        result => result,
     }
 }
 
+/// Load the config for startup, reporting every problem and exiting if it is invalid
+pub fn load_config_or_exit(
+    path: &Path,
+    profile: Option<&str>,
+    overrides: &[ConfigOverride],
+) -> Config {
+    load_config(path, profile, overrides).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(1);
+    })
+}
+
+/// Save the changes `edit` makes to the config in the file at `path`, or to the named profile
+/// within it. The edit starts from what the file currently gives the profile, without command line
+/// overrides, and only the keys it changes are written, so comments, formatting and every other
+/// key and profile in the file are kept as they are.
+pub fn save_config(
+    path: &Path,
+    profile: Option<&str>,
+    edit: impl FnOnce(&mut Config),
+) -> Result<(), ConfigError> {
+    use toml_edit::{DocumentMut, Item, Table, Value};
+
+    let source = match fs::read_to_string(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        result => result.map_err(ConfigError::Io)?,
+    };
+    // refuse to touch a file that doesn't load, the user may be halfway through editing it
+    parse_config(&source, None, &[])?;
+    let mut document: DocumentMut = source.parse().expect("source already parsed as toml");
+
+    // the profile doesn't exist yet when saving it for the first time, so compare against the top
+    // level keys instead
+    let profile_exists = profile.is_some_and(|name| {
+        document
+            .get(PROFILE_KEY)
+            .and_then(|profiles| profiles.get(name))
+            .is_some()
+    });
+    let mut config = parse_config(&source, profile.filter(|_| profile_exists), &[])?;
+    let current = toml::Table::try_from(&config).expect("config serializes to a table");
+    edit(&mut config);
+    let updated = toml::Table::try_from(&config).expect("config serializes to a table");
+
+    let table = match profile {
+        Some(name) => {
+            let profiles = document
+                .entry(PROFILE_KEY)
+                .or_insert_with(|| {
+                    let mut profiles = Table::new();
+                    profiles.set_implicit(true);
+                    Item::Table(profiles)
+                })
+                .as_table_like_mut()
+                .expect("profiles are checked to be a table by parse_config");
+            profiles
+                .entry(name)
+                .or_insert(Item::Table(Table::new()))
+                .as_table_like_mut()
+                .expect("profiles are checked to be tables by parse_config")
+        }
+        None => document.as_table_mut() as &mut dyn TableLike,
+    };
+    for (key, value) in updated {
+        if current.get(&key) == Some(&value) {
+            continue;
+        }
+        let mut value: Value = value
+            .to_string()
+            .parse()
+            .expect("toml values reparse as toml values");
+        match table.get_mut(&key) {
+            // replace the value in place, keeping the comments around the key and value
+            Some(item) => {
+                if let Some(old) = item.as_value() {
+                    *value.decor_mut() = old.decor().clone();
+                }
+                *item = Item::Value(value);
+            }
+            None => {
+                table.insert(&key, Item::Value(value));
+            }
+        }
+    }
+
+    // the config directory doesn't exist yet the first time the default config is saved
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(ConfigError::Io)?;
+    }
+    fs::write(path, document.to_string()).map_err(ConfigError::Io)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::args::Args;
 
     #[test]
     fn test_load_config_path_dne() {
-        let default_conf = load_config(&String::from("path_does_not_exist"), false);
+        let default_conf = load_config(Path::new("path_does_not_exist"), None, &[]).unwrap();
         assert_eq!(default_conf, Config::default());
     }
 
     #[test]
     fn test_load_example_config() {
-        load_config(&String::from("test/config.toml"), false);
+        load_config(Path::new("test/config.toml"), None, &[]).unwrap();
     }
 
     #[test]
     fn test_load_config_error() {
-        load_config(&String::from("test/config_error.toml"), false);
+        let Err(ConfigError::Invalid(problems)) =
+            load_config(Path::new("test/config_error.toml"), None, &[])
+        else {
+            panic!("a malformed config should not load");
+        };
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(1));
+    }
+
+    #[test]
+    fn test_parse_config_reports_every_problem() {
+        let Err(ConfigError::Invalid(problems)) =
+            read_config(Path::new("test/config_invalid.toml"), None, &[])
+        else {
+            panic!("an invalid config should not load");
+        };
+        let lines: Vec<Option<usize>> = problems.iter().map(|p| p.line).collect();
+        assert_eq!(
+            lines,
+            vec![Some(3), Some(4), Some(5), Some(6), Some(7), Some(8)]
+        );
+        assert!(problems[0].message.contains("unknown key `n_pixels`"));
+        assert!(problems[1].message.contains("`fps`"));
+        assert!(problems[2].message.contains("`min_freq_hz`"));
+        assert!(problems[3].message.contains("nyquist"));
+        assert!(problems[4].message.contains("power of two"));
+        assert!(problems[5].message.contains("at least 3"));
+    }
+
+    #[test]
+    fn test_profile_and_overrides_layer_over_top_level_keys() {
+        let path = Path::new("test/config_profiles.toml");
+        let config = read_config(path, None, &[]).unwrap();
+        assert_eq!((config.fps, config.n_points), (60, 100));
+
+        let config = read_config(path, Some("livingroom"), &[]).unwrap();
+        assert_eq!(config.device_ip, "192.168.0.151");
+        assert_eq!((config.fps, config.n_points), (60, 144));
+
+        let args = Args {
+            device_port: Some(8888),
+            overrides: vec![
+                parse_override("n_points=50").unwrap(),
+                parse_override("device_ip=10.0.0.2").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let config = read_config(path, Some("livingroom"), &args.config_overrides()).unwrap();
+        assert_eq!(config.device_ip, "10.0.0.2");
+        assert_eq!((config.device_port, config.n_points), (8888, 50));
+    }
+
+    #[test]
+    fn test_profile_and_override_problems_are_reported() {
+        let source = "fps = 60\n\n[profile.desk]\nfps = \"fast\"\n";
+        let overrides = [parse_override("n_pixels=12").unwrap()];
+        let Err(ConfigError::Invalid(problems)) = parse_config(source, Some("kitchen"), &overrides)
+        else {
+            panic!("an invalid profile and override should not load");
+        };
+        let lines: Vec<Option<usize>> = problems.iter().map(|p| p.line).collect();
+        assert_eq!(lines, vec![None, None, Some(4)]);
+        assert!(problems[0].message.contains("no profile named `kitchen`"));
+        assert!(problems[1]
+            .message
+            .contains("command line: unknown key `n_pixels`"));
+        assert!(problems[2].message.contains("profile `desk`"));
+    }
+
+    #[test]
+    fn test_time_constants_are_validated() {
+        let source = "scroll_gain_time = { attack_ms = -1, release_ms = 50 }\n\
+                      power_gain_time = { attack_ms = 20 }\n";
+        let Err(ConfigError::Invalid(problems)) = parse_config(source, None, &[]) else {
+            panic!("invalid time constants should not load");
+        };
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line, Some(1));
+        assert!(problems[0].message.contains("non-negative"));
+        assert_eq!(problems[1].line, Some(2));
+        assert!(problems[1].message.contains("release_ms"));
+
+        let config = parse_config(
+            "spectrum_red_time = { attack_ms = 10, release_ms = 0 }\n",
+            None,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.spectrum_red_time, TimeConstant::new(10.0, 0.0));
+    }
+
+    #[test]
+    fn test_latency_offset_goes_either_way_within_bounds() {
+        let config =
+            parse_config("playback = true\nlatency_offset_ms = -120\n", None, &[]).unwrap();
+        assert_eq!(config.latency_offset_ms, -120);
+
+        let Err(ConfigError::Invalid(problems)) =
+            parse_config("playback = true\nlatency_offset_ms = -2500\n", None, &[])
+        else {
+            panic!("an offset beyond the bounds should not load");
+        };
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(2));
+        assert!(problems[0].message.contains("-2500"));
+
+        // only audio the renderer plays back itself can be held back
+        let Err(ConfigError::Invalid(problems)) =
+            parse_config("latency_offset_ms = -120\n", None, &[])
+        else {
+            panic!("a negative offset without playback should not load");
+        };
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("playback"));
+    }
+
+    #[test]
+    fn test_frequency_scale_is_validated() {
+        let config = parse_config("frequency_scale = \"log\"\n", None, &[]).unwrap();
+        assert_eq!(config.frequency_scale, FrequencyScale::Log);
+
+        // an octave doesn't hold enough semitones for 12 bands pinned to notes
+        let source = "frequency_scale = \"notes\"\n\
+                      min_freq_hz = 440\n\
+                      max_freq_hz = 880\n\
+                      n_mel_bands = 12\n";
+        let Err(ConfigError::Invalid(problems)) = parse_config(source, None, &[]) else {
+            panic!("too many note bands for the range should not load");
+        };
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(1));
+        assert!(problems[0].message.contains("semitone"));
+    }
+
+    #[test]
+    fn test_input_filters_are_validated() {
+        let config = parse_config(
+            "highpass_hz = 30\neq = [{ freq_hz = 80, gain_db = -6, q = 1 }]\n",
+            None,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.eq.len(), 1);
+
+        let source = "pre_emphasis = 1.5\n\
+                      eq = [{ freq_hz = 80, gain_db = -6, q = 1 }, { freq_hz = 90000, gain_db = 3, q = 1 }]\n";
+        let Err(ConfigError::Invalid(problems)) = parse_config(source, None, &[]) else {
+            panic!("invalid input filters should not load");
+        };
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line, Some(1));
+        assert_eq!(problems[1].line, Some(2));
+        assert!(problems[1].message.contains("band 2: freq_hz"));
+    }
+
+    #[test]
+    fn test_frames_need_a_sample_each() {
+        let source = "mic_rate = 48000\nfps = 50000\n";
+        let Err(ConfigError::Invalid(problems)) = parse_config(source, None, &[]) else {
+            panic!("more frames than samples should not load");
+        };
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(2));
+        assert!(problems[0].message.contains("mic_rate"));
+    }
+
+    #[test]
+    fn test_flash_rate_is_capped() {
+        let config = parse_config("max_flash_hz = 2.9\n", None, &[]).unwrap();
+        assert_eq!(config.max_flash_hz, 2.9);
+        for source in ["max_flash_hz = 3\n", "max_flash_hz = 0\n"] {
+            let Err(ConfigError::Invalid(problems)) = parse_config(source, None, &[]) else {
+                panic!("{source} should not load");
+            };
+            assert!(problems[0].message.contains("max_flash_hz"));
+        }
+    }
+
+    #[test]
+    fn test_parse_override() {
+        assert_eq!(
+            parse_override("fps=30").unwrap().value,
+            toml::Value::Integer(30)
+        );
+        assert_eq!(
+            parse_override("preset = power").unwrap(),
+            ConfigOverride {
+                key: String::from("preset"),
+                value: toml::Value::String(String::from("power")),
+            }
+        );
+        assert!(parse_override("fps").is_err());
+    }
+
+    #[test]
+    fn test_save_config_keeps_comments_and_other_keys() {
+        let path = std::env::temp_dir().join(format!("save_config_{}.toml", std::process::id()));
+        let source = "# strip in the hallway\nfps = 60 # smooth enough\nn_points = 100\n\n[profile.desk]\nfps = 30\n";
+        fs::write(&path, source).unwrap();
+
+        let edit = |config: &mut Config| {
+            config.fps = 50;
+            config.left_slider_start = 300;
+        };
+        let mut config = read_config(&path, None, &[]).unwrap();
+        edit(&mut config);
+        save_config(&path, None, edit).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(
+            saved.starts_with("# strip in the hallway\nfps = 50 # smooth enough\nn_points = 100\n")
+        );
+        assert!(saved.contains("[profile.desk]\nfps = 30\n"));
+        assert_eq!(read_config(&path, None, &[]).unwrap(), config);
+
+        // a new profile only records what differs from the top level keys
+        config.display_mode = DisplayMode::Rolling;
+        save_config(&path, Some("hall"), |config| {
+            config.display_mode = DisplayMode::Rolling
+        })
+        .unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.ends_with("[profile.hall]\ndisplay_mode = \"rolling\"\n"));
+        assert_eq!(read_config(&path, Some("hall"), &[]).unwrap(), config);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_into_an_existing_profile_keeps_its_keys() {
+        let path = std::env::temp_dir().join(format!("save_profile_{}.toml", std::process::id()));
+        let source = "fps = 60
+n_points = 100
+
+[profile.desk]
+fps = 30
+n_points = 60
+";
+        fs::write(&path, source).unwrap();
+
+        // saving from the top level config only writes what was changed, not the top level fps
+        // and n_points over the ones the profile set
+        save_config(&path, Some("desk"), |config| config.left_slider_start = 300).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(
+            saved.ends_with("[profile.desk]\nfps = 30\nn_points = 60\nleft_slider_start = 300\n")
+        );
+        let config = read_config(&path, Some("desk"), &[]).unwrap();
+        assert_eq!((config.fps, config.n_points), (30, 60));
+        assert_eq!(
+            read_config(&path, None, &[]).unwrap().left_slider_start,
+            Config::default().left_slider_start
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_config_creates_missing_directories() {
+        let dir = std::env::temp_dir().join(format!("save_config_dir_{}", std::process::id()));
+        let path = dir.join("audio-reactive-led-strip").join("config.toml");
+        save_config(&path, None, |config| config.fps = 50).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fps = 50\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(Config::default().validate().is_empty());
+    }
+
+    #[test]
+    fn test_watch_config_skips_invalid_revisions() {
+        let path = std::env::temp_dir().join(format!("watch_config_{}.toml", std::process::id()));
+        fs::write(&path, "fps = 60\n").unwrap();
+        let updates = watch_config(path.clone(), None, vec![]);
+
+        // an invalid revision is reported and never reaches the receiver
+        fs::write(&path, "fps = \"sixty\"\n").unwrap();
+        thread::sleep(CONFIG_POLL_INTERVAL * 3);
+        assert!(updates.try_recv().is_err());
+
+        fs::write(&path, "fps = 30\nn_mel_bands = 12\n").unwrap();
+        let config = updates.recv_timeout(CONFIG_POLL_INTERVAL * 4).unwrap();
+        assert_eq!(config.fps, 30);
+        assert_eq!(config.n_mel_bands, 12);
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,34 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// How the gui draws the rendered frames
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayMode {
+    Rolling,
+    Power,
+    Frequency,
+}
+
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::Rolling,
+        DisplayMode::Power,
+        DisplayMode::Frequency,
+    ];
+}
+
+impl Display for DisplayMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DisplayMode::Rolling => "Rolling",
+                DisplayMode::Power => "Power",
+                DisplayMode::Frequency => "Frequency",
+            }
+        )
+    }
+}
@@ -1,225 +1,348 @@
-use std::sync::Arc;
+mod agc;
+mod chroma;
+pub mod effect;
+mod filter;
+mod gate;
+pub mod history;
+mod onset;
+mod real_fft;
+mod scale;
+mod tempo;
+mod window;
 
 use ndarray::{s, Array, Array1, Array2, Axis, Dimension, Ix1, Ix2, NewAxis};
-use rustfft::{
-    num_complex::{Complex64, ComplexFloat},
-    Fft, FftPlanner,
-};
 
-use crate::config::Config;
-
-/*
-===To add new transforms===
-
-1. create a transform function with the signature
-fn(display_buffer: &mut Array2<f64>,
-y: &mut Array1<f64>,
-gain: &mut Array1<f64>,
-alpha_decay: f64,
-alpha_rise: f64)
-
-2. create a preset enum
-
-3. assign the preset enum to the function in the apply_transform match
-*/
+use crate::config::{Config, TimeConstant};
+use agc::Agc;
+pub use agc::GainMode;
+use chroma::{ChromaFilter, N_PITCH_CLASSES};
+pub use effect::{Effect, Preset};
+pub use filter::{EqBand, InputFilter};
+use gate::NoiseGate;
+use history::History;
+pub use onset::Onset;
+use onset::OnsetDetector;
+use real_fft::RealFft;
+pub use scale::FrequencyScale;
+pub use tempo::Tempo;
+use tempo::TempoTracker;
+pub use window::Window;
+
+/// Analyzes windows of audio into the [`Analysis`] that effects render
 pub struct Dsp {
-    gain: ExpFilterArr<Ix1>,
-    p_filt: ExpFilterArr<Ix2>,
-    common_mode: ExpFilterArr<Ix1>,
-    r_filt: ExpFilterArr<Ix1>,
-    g_filt: ExpFilterArr<Ix1>,
-    b_filt: ExpFilterArr<Ix1>,
-    prev_spectrum: Array1<f64>,
     gaussian_kernel1: Array1<f64>,
-    gaussian_kernel2: Array1<f64>,
     mel_bank: MelBank,
     mel_gain: ExpFilterArr<Ix1>,
     mel_smoothing: ExpFilterArr<Ix1>,
-    fft: Arc<dyn Fft<f64>>,
+    agc: Agc,
+    // gain and smoothing of the left and right channels' mel spectra
+    channel_gain: [ExpFilterArr<Ix1>; 2],
+    channel_smoothing: [ExpFilterArr<Ix1>; 2],
+    chroma_filter: ChromaFilter,
+    chroma_smoothing: ExpFilterArr<Ix1>,
+    fft: RealFft,
+    window: Array1<f64>,
+    gate: NoiseGate,
+    onset_detector: OnsetDetector,
+    tempo_tracker: TempoTracker,
+    analysis: Analysis,
     config: Config,
 }
 
-#[derive(Clone)]
-pub enum Preset {
-    Scroll,
-    Power,
-    Spectrum,
+/// Everything extracted from the latest frame of audio, for effects to react to
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The mel spectrum after gain normalization and smoothing
+    pub mel: Array1<f64>,
+    /// Smoothed energy of the 12 pitch classes, from C to B, with the strongest at 1
+    pub chroma: Array1<f64>,
+    pub onset: Onset,
+    pub tempo: Tempo,
+    /// Output level of the noise gate, 1 while there is sound and fading to 0 in silence
+    pub gate: f64,
+    /// The left and right channels, for stereo input only
+    pub stereo: Option<Stereo>,
+}
+
+/// The stereo image of the latest frame of audio
+#[derive(Debug, Clone)]
+pub struct Stereo {
+    /// The mel spectra of the left and right channels, normalized and smoothed like `mel`
+    pub mel: [Array1<f64>; 2],
+    /// Share of the energy in the side signal, from 0 for mono through 0.5 for a single channel
+    /// to 1 for channels in opposite phase
+    pub width: f64,
+}
+
+impl Analysis {
+    fn new(n_mel_bands: usize) -> Self {
+        Self {
+            mel: Array1::zeros(n_mel_bands),
+            chroma: Array1::zeros(N_PITCH_CLASSES),
+            onset: Onset::none(n_mel_bands),
+            tempo: Tempo::none(),
+            gate: 0.0,
+            stereo: None,
+        }
+    }
 }
 
 impl Dsp {
     pub fn new(config: Config) -> Self {
         Self {
-            gain: ExpFilterArr::<Ix1>::new(config.n_mel_bands as usize, 0.01, 0.2, 0.2),
-            p_filt: ExpFilterArr::<Ix2>::new(
-                (config.n_points / 2 + config.n_points % 2) as usize,
-                1.,
-                0.99,
-                0.1,
-            ),
-            common_mode: ExpFilterArr::<Ix1>::new(
-                (config.n_mel_bands / 2) as usize,
-                0.01,
-                0.99,
-                0.01,
-            ),
-            r_filt: ExpFilterArr::<Ix1>::new((config.n_points / 2) as usize, 0.01, 0.2, 0.99),
-            g_filt: ExpFilterArr::<Ix1>::new((config.n_points / 2) as usize, 0.01, 0.05, 0.3),
-            b_filt: ExpFilterArr::<Ix1>::new((config.n_points / 2) as usize, 0.01, 0.1, 0.5),
-            prev_spectrum: Array1::zeros(config.n_mel_bands as usize),
             gaussian_kernel1: gaussian_kernel(0.2, 0, 1), // TODO: determine whether radius 1 is what we want
-            gaussian_kernel2: gaussian_kernel(0.4, 0, 1), // TODO: determine whether radius 1 is what we want
-            mel_bank: create_mel_bank(
+            mel_bank: create_filter_bank(
+                config.frequency_scale,
                 config.mic_rate,
                 config.n_fft_bins / 2,
                 config.n_mel_bands,
                 config.min_freq_hz,
                 config.max_freq_hz,
             ),
-            mel_gain: ExpFilterArr::<Ix1>::new(config.n_mel_bands as usize, 0.1, 0.01, 0.99),
-            mel_smoothing: ExpFilterArr::<Ix1>::new(config.n_mel_bands as usize, 0.1, 0.5, 0.99),
-            fft: new_rfft(config.n_fft_bins),
+            mel_gain: ExpFilterArr::<Ix1>::new(
+                config.n_mel_bands as usize,
+                0.1,
+                config.mel_gain_time,
+                config.fps,
+            ),
+            mel_smoothing: ExpFilterArr::<Ix1>::new(
+                config.n_mel_bands as usize,
+                0.1,
+                config.mel_smoothing_time,
+                config.fps,
+            ),
+            agc: Agc::new(&config),
+            channel_gain: [0, 1].map(|_| {
+                ExpFilterArr::<Ix1>::new(
+                    config.n_mel_bands as usize,
+                    0.1,
+                    config.mel_gain_time,
+                    config.fps,
+                )
+            }),
+            channel_smoothing: [0, 1].map(|_| {
+                ExpFilterArr::<Ix1>::new(
+                    config.n_mel_bands as usize,
+                    0.1,
+                    config.mel_smoothing_time,
+                    config.fps,
+                )
+            }),
+            chroma_filter: ChromaFilter::new(
+                config.mic_rate,
+                config.n_fft_bins,
+                config.min_freq_hz,
+                config.max_freq_hz,
+            ),
+            chroma_smoothing: ExpFilterArr::<Ix1>::new(
+                N_PITCH_CLASSES,
+                0.0,
+                config.chroma_time,
+                config.fps,
+            ),
+            fft: RealFft::new(config.n_fft_bins as usize),
+            window: config.window.coefficients(config.n_fft_bins as usize),
+            gate: NoiseGate::new(
+                config.min_volume_threshold,
+                config.gate_release_ms,
+                config.fps,
+            ),
+            onset_detector: OnsetDetector::new(config.n_mel_bands as usize, config.fps),
+            tempo_tracker: TempoTracker::new(config.fps),
+            analysis: Analysis::new(config.n_mel_bands as usize),
             config,
         }
     }
-    pub fn apply_transform_inplace(&mut self, preset: Preset, display_values: &mut Array2<f64>) {
-        match preset {
-            Preset::Scroll => self.visualize_scroll(display_values),
-            Preset::Power => self.visualize_power(display_values),
-            Preset::Spectrum => self.visualize_spectrum(display_values),
-        };
-    }
 
-    fn visualize_scroll(&mut self, display_values: &mut Array2<f64>) {
-        let mut display_slice = display_values
-            .slice(s![(self.config.n_points / 2) as usize.., ..])
-            .to_owned();
-        let mut y = self.mel_smoothing.current.clone();
-        // y = y**2.0
-        y.map_inplace(|x| *x = x.powi(2));
-        // update gain
-        self.gain.update(&y);
-        // y /= gain.value
-        // y *= 255
-        y.zip_mut_with(&self.gain.current, |y, g| *y = 255.0 * (*y) / g);
-
-        // scrolling effect
-        // p[1:, :] = p[:-1, :]
-        // p *= 0.98
-        for i in 1..display_slice.shape()[0] - 1 {
-            let left_pixels = display_slice.slice(s![i - 1, ..]).to_owned() * 0.98;
-            display_slice.slice_mut(s![i, ..]).assign(&left_pixels);
+    /// Move to a new config, rebuilding only the filter bank, fft plan and filter state whose
+    /// parameters changed. Everything else keeps its running state.
+    pub fn update_config(&mut self, config: Config) {
+        if self.config.n_fft_bins != config.n_fft_bins {
+            self.fft = RealFft::new(config.n_fft_bins as usize);
         }
-        // apply gaussian filter
-        let mut filter_display_buffer = correlate_1d(&display_slice, &self.gaussian_kernel1);
-
-        // create one new color originating at the center
-        for i in 0..3 {
-            let s = y.slice(s![i * y.shape()[0] / 3..(i + 1) * y.shape()[0] / 3]);
-            let mut max: f64 = 0.0;
-            s.map(|x| {
-                max = f64::max(max, *x);
-            });
+        if self.config.n_fft_bins != config.n_fft_bins || self.config.window != config.window {
+            self.window = config.window.coefficients(config.n_fft_bins as usize);
+        }
+        if self.config.mel_bank_changed(&config) {
+            self.mel_bank = create_filter_bank(
+                config.frequency_scale,
+                config.mic_rate,
+                config.n_fft_bins / 2,
+                config.n_mel_bands,
+                config.min_freq_hz,
+                config.max_freq_hz,
+            );
+            self.chroma_filter = ChromaFilter::new(
+                config.mic_rate,
+                config.n_fft_bins,
+                config.min_freq_hz,
+                config.max_freq_hz,
+            );
+        }
+        if self.config.n_mel_bands != config.n_mel_bands {
+            let n_mel_bands = config.n_mel_bands as usize;
+            self.mel_gain.resize(n_mel_bands);
+            self.mel_smoothing.resize(n_mel_bands);
+            for filter in self.channel_gain.iter_mut() {
+                filter.resize(n_mel_bands);
+            }
+            for filter in self.channel_smoothing.iter_mut() {
+                filter.resize(n_mel_bands);
+            }
+            self.analysis = Analysis::new(n_mel_bands);
+        }
+        if self.config.n_mel_bands != config.n_mel_bands || self.config.fps != config.fps {
+            self.onset_detector = OnsetDetector::new(config.n_mel_bands as usize, config.fps);
+        }
+        if self.config.fps != config.fps {
+            self.tempo_tracker = TempoTracker::new(config.fps);
+        }
+        if self.config.min_volume_threshold != config.min_volume_threshold
+            || self.config.gate_release_ms != config.gate_release_ms
+            || self.config.fps != config.fps
+        {
+            self.gate = NoiseGate::new(
+                config.min_volume_threshold,
+                config.gate_release_ms,
+                config.fps,
+            );
+        }
+        self.set_time_constants(&config);
+        self.agc = Agc::new(&config);
+        self.config = config;
+    }
 
-            filter_display_buffer[[0, i]] = max;
+    fn set_time_constants(&mut self, config: &Config) {
+        let fps = config.fps;
+        self.mel_gain.set_time_constant(config.mel_gain_time, fps);
+        self.mel_smoothing
+            .set_time_constant(config.mel_smoothing_time, fps);
+        self.chroma_smoothing
+            .set_time_constant(config.chroma_time, fps);
+        for filter in self.channel_gain.iter_mut() {
+            filter.set_time_constant(config.mel_gain_time, fps);
         }
+        for filter in self.channel_smoothing.iter_mut() {
+            filter.set_time_constant(config.mel_smoothing_time, fps);
+        }
+    }
 
-        // scroll display
-        display_values.assign(&ndarray::concatenate![
-            Axis(0),
-            filter_display_buffer.slice(s![(self.config.n_points % 2) as usize..,..;-1]),
-            filter_display_buffer,
-        ]);
+    /// Analyze the latest window of audio, updating the smoothed mel spectrum, onsets and tempo
+    /// that effects draw from
+    pub fn analyze(&mut self, history: &History) -> &Analysis {
+        let gate = self.gate.update(history.rms());
+        self.exec_rfft(history.iter());
+        let mut mel = self.get_mel_repr(self.fft.magnitudes());
+        let chroma = self.chroma_filter.apply(self.fft.magnitudes());
+        let mut onset = self.onset_detector.update(&mel);
+        if !self.gate.is_open() {
+            onset.beat = false;
+            onset.confidence = 0.0;
+        }
+        let tempo = self.tempo_tracker.update(&onset);
+        // keep room noise out of the adaptive gain while the gate is closed
+        if self.gate.is_open() {
+            self.gain_and_smooth(&mut mel);
+            self.chroma_smoothing.update(&chroma);
+        }
+        self.analysis = Analysis {
+            mel: self.mel_smoothing.current.clone(),
+            chroma: self.chroma_smoothing.current.clone(),
+            onset,
+            tempo,
+            gate,
+            stereo: None,
+        };
+        &self.analysis
     }
-    fn visualize_power(&mut self, display_values: &mut Array2<f64>) {
-        let mut y = self.mel_smoothing.current.clone();
-        self.gain.update(&y);
-        let mut display_slice = display_values
-            .slice(s![(self.config.n_points / 2) as usize.., ..])
-            .to_owned();
-
-        // y /= gain.value
-        // y *= float(config.n_pixels // 2) - 1)
-        y.zip_mut_with(&self.gain.current, |y, g| {
-            *y *= ((self.config.n_points / 2) - 1) as f64 / g;
-        });
 
-        // map color channels according to energy in different frequency bands
-        let scale = 0.9;
-        for i in 0..3 {
-            let s = y
-                .slice(s![i * y.shape()[0] / 3..(i + 1) * y.shape()[0] / 3])
-                .map(|x| x.powf(scale));
-            let mean = s.mean().unwrap() as usize;
-            println!("{}", mean);
-            display_slice.slice_mut(s![..mean, i]).fill(255.0);
-            display_slice.slice_mut(s![mean.., i]).fill(0.0);
+    /// Analyze the left and right channels of the frame just passed to `analyze`, for effects
+    /// that show the stereo image
+    pub fn analyze_stereo(&mut self, left: &History, right: &History) -> &Analysis {
+        for (channel, history) in [left, right].into_iter().enumerate() {
+            self.exec_rfft(history.iter());
+            let mut mel = self.get_mel_repr(self.fft.magnitudes());
+            if self.gate.is_open() {
+                gain_and_smooth(
+                    &mut mel,
+                    &self.gaussian_kernel1,
+                    &mut self.channel_gain[channel],
+                    &mut self.channel_smoothing[channel],
+                    &self.agc,
+                );
+            }
         }
 
-        self.p_filt.update(&display_slice);
-        display_slice.map_inplace(|x| {
-            *x = x.round();
+        let (mut mid_energy, mut side_energy) = (0.0, 0.0);
+        for (l, r) in left.iter().zip(right.iter()) {
+            mid_energy += ((l + r) / 2.0).powi(2);
+            side_energy += ((l - r) / 2.0).powi(2);
+        }
+        self.analysis.stereo = Some(Stereo {
+            mel: [
+                self.channel_smoothing[0].current.clone(),
+                self.channel_smoothing[1].current.clone(),
+            ],
+            width: if mid_energy + side_energy > 0.0 {
+                side_energy / (mid_energy + side_energy)
+            } else {
+                0.0
+            },
         });
-        display_slice.assign(&correlate_1d(&display_slice, &self.gaussian_kernel2));
-
-        display_values.assign(&ndarray::concatenate![
-            Axis(0),
-            display_slice.slice(s![(self.config.n_points % 2) as usize..,..;-1]),
-            display_slice
-        ]);
+        &self.analysis
     }
-    fn visualize_spectrum(&mut self, display_buffer: &mut Array2<f64>) {
-        // TODO: need to do interpolation up here?
-        let y = self.mel_smoothing.current.clone();
-        self.common_mode.update(&y);
-        //diff = y - self.prev_spectrum
-        let diff = &y - &self.prev_spectrum;
-        self.prev_spectrum.assign(&y);
-
-        // color channel mappings
-        self.r_filt.update(&(&y - &self.common_mode.current));
-        let r = &self.r_filt.current;
-        let g = diff.map(|x| x.abs());
-        self.b_filt.update(&y);
-        let b = &self.b_filt.current;
-
-        // Mirror the color channels for symmetric output
-        let r = ndarray::concatenate![Axis(0), r.slice(s![..;-1]).to_owned(), r.to_owned()];
-        let g = ndarray::concatenate![Axis(0), g.slice(s![..;-1]), g];
-        let b = ndarray::concatenate![Axis(0), b.slice(s![..;-1]).to_owned(), b.to_owned()];
 
-        display_buffer.assign(&ndarray::stack![Axis(0), r, g, b]);
+    /// The analysis of the latest frame of audio
+    pub fn analysis(&self) -> &Analysis {
+        &self.analysis
     }
 
-    pub fn exec_rfft(&self, buffer: &Array1<f64>) -> Array1<f64> {
-        let mut complex_buffer: Array1<Complex64> =
-            buffer.iter().map(|x| Complex64::new(*x, 0.0)).collect();
-
-        self.fft.process(complex_buffer.as_slice_mut().unwrap());
-        complex_buffer
-            .mapv(Complex64::abs)
-            .slice(s![..buffer.len() / 2])
-            .to_owned()
+    /// Magnitudes of the first n_fft_bins / 2 bins of the windowed spectrum of `samples`, which
+    /// must yield exactly n_fft_bins values
+    pub fn exec_rfft(&mut self, samples: impl IntoIterator<Item = f64>) -> &Array1<f64> {
+        self.fft.transform(
+            samples
+                .into_iter()
+                .zip(&self.window)
+                .map(|(sample, w)| sample * w),
+        );
+        self.fft.magnitudes()
     }
 
     pub fn gain_and_smooth(&mut self, mel: &mut Array1<f64>) {
-        mel.map_mut(|x| *x = x.powi(2));
-        let filtered_mel = self.gaussian_filter1d_single(mel);
-        self.mel_gain.update(&filtered_mel);
-        mel.zip_mut_with(&self.mel_gain.current, |m, g| *m /= g);
-        self.mel_smoothing.update(mel);
+        gain_and_smooth(
+            mel,
+            &self.gaussian_kernel1,
+            &mut self.mel_gain,
+            &mut self.mel_smoothing,
+            &self.agc,
+        );
     }
 
     pub fn get_mel_repr(&self, audio: &Array1<f64>) -> Array1<f64> {
         self.mel_bank.y.dot(audio)
     }
 
+    #[cfg(test)]
     fn gaussian_filter1d(&self, input: &Array2<f64>) -> Array2<f64> {
         correlate_1d(input, &self.gaussian_kernel1.slice(s![..;-1]).to_owned())
     }
+}
 
-    fn gaussian_filter1d_single(&self, input: &Array1<f64>) -> Array1<f64> {
-        correlate_1d_single(input, &self.gaussian_kernel1.slice(s![..;-1]).to_owned())
-    }
+/// Square `mel`, apply the gain `agc` sets for the level tracked by `gain` and feed it to
+/// `smoothing`, blurring the level across neighbouring bands with `kernel`
+fn gain_and_smooth(
+    mel: &mut Array1<f64>,
+    kernel: &Array1<f64>,
+    gain: &mut ExpFilterArr<Ix1>,
+    smoothing: &mut ExpFilterArr<Ix1>,
+    agc: &Agc,
+) {
+    mel.map_mut(|x| *x = x.powi(2));
+    let filtered_mel = correlate_1d_single(mel, &kernel.slice(s![..;-1]).to_owned());
+    gain.update(&filtered_mel);
+    mel.zip_mut_with(&gain.current, |m, level| *m *= agc.gain(*level));
+    smoothing.update(mel);
 }
 
 fn hertz_to_mel(hertz: f64) -> f64 {
@@ -227,19 +350,7 @@ fn hertz_to_mel(hertz: f64) -> f64 {
 }
 
 fn mel_to_hertz(mel: f64) -> f64 {
-    700.0 * (10.0.powf(mel / 2595.0)) - 700.0
-}
-
-/**
- * Single exponential filter value. Returns the new value to the stack
- */
-fn exp_filter_single(current_val: f64, new_val: f64, alpha_decay: f64, alpha_rise: f64) -> f64 {
-    let alpha = if new_val > current_val {
-        alpha_rise
-    } else {
-        alpha_decay
-    };
-    alpha * new_val + (1.0 - alpha) * current_val
+    700.0 * (10.0_f64.powf(mel / 2595.0)) - 700.0
 }
 
 struct ExpFilterArr<T>
@@ -247,18 +358,32 @@ where
     T: Dimension,
 {
     current: Array<f64, T>,
+    init: f64,
     alpha_rise: f64,
     alpha_decay: f64,
 }
 
+impl<T: Dimension> ExpFilterArr<T> {
+    /// Follow `time` when updated `fps` times a second, keeping the current value
+    pub fn set_time_constant(&mut self, time: TimeConstant, fps: u32) {
+        (self.alpha_rise, self.alpha_decay) = time.alphas(fps);
+    }
+}
+
 impl ExpFilterArr<Ix2> {
-    pub fn new(size: usize, init: f64, alpha_rise: f64, alpha_decay: f64) -> Self {
+    pub fn new(size: usize, init: f64, time: TimeConstant, fps: u32) -> Self {
+        let (alpha_rise, alpha_decay) = time.alphas(fps);
         Self {
             current: Array::<f64, Ix2>::ones((size, 3)) * init,
+            init,
             alpha_rise,
             alpha_decay,
         }
     }
+    /// Reset the filter to its initial value with a new number of rows
+    pub fn resize(&mut self, size: usize) {
+        self.current = Array::<f64, Ix2>::ones((size, 3)) * self.init;
+    }
     pub fn update(&mut self, new: &Array2<f64>) {
         assert_eq!(self.current.shape(), new.shape());
         self.current.indexed_iter_mut().for_each(|(i, c)| {
@@ -273,13 +398,19 @@ impl ExpFilterArr<Ix2> {
 }
 
 impl ExpFilterArr<Ix1> {
-    pub fn new(size: usize, init: f64, alpha_rise: f64, alpha_decay: f64) -> Self {
+    pub fn new(size: usize, init: f64, time: TimeConstant, fps: u32) -> Self {
+        let (alpha_rise, alpha_decay) = time.alphas(fps);
         Self {
             current: Array::<f64, Ix1>::ones(size) * init,
+            init,
             alpha_rise,
             alpha_decay,
         }
     }
+    /// Reset the filter to its initial value with a new length
+    pub fn resize(&mut self, size: usize) {
+        self.current = Array::<f64, Ix1>::ones(size) * self.init;
+    }
     pub fn update(&mut self, new: &Array1<f64>) {
         assert_eq!(self.current.len(), new.len());
         self.current.indexed_iter_mut().for_each(|(i, c)| {
@@ -293,6 +424,7 @@ impl ExpFilterArr<Ix1> {
     }
 }
 
+#[cfg(test)]
 fn exp_filter_array(
     current: &ndarray::Array1<f64>,
     new: &ndarray::Array1<f64>,
@@ -314,10 +446,6 @@ fn exp_filter_array(
         .collect()
 }
 
-pub fn new_rfft(fft_size: u32) -> Arc<dyn Fft<f64>> {
-    FftPlanner::new().plan_fft_forward(fft_size as usize)
-}
-
 /**
  * A transformation matrix for mel spectrum
  * mel\_y: the transformation matrix
@@ -329,24 +457,22 @@ pub struct MelBank {
 }
 
 /**
-* Generate a MelBank according to parameters
+* Generate a MelBank of triangular filters spaced evenly on a frequency scale
+* scale: the frequency scale the bands are spaced on, the mel scale for a classic mel bank
 * mic\_rate: the sampling rate of the microphone
-* n\_rolling\_history: the number of samples in the rolling history
+* n\_fft\_bins: the number of fft magnitudes the bank is applied to
 */
-pub fn create_mel_bank(
+pub fn create_filter_bank(
+    scale: FrequencyScale,
     mic_rate: u32,
     n_fft_bins: u32,
     n_mel_bands: u32,
     min_freq_hz: u32,
     max_freq_hz: u32,
 ) -> MelBank {
-    // generate centerfrequencies and band eges for a mel filter bank
-    let max_freq_mel = hertz_to_mel(max_freq_hz as f64);
-    let min_freq_mel = hertz_to_mel(min_freq_hz as f64);
-    let delta_mel = (max_freq_mel - min_freq_mel).abs() / (n_mel_bands as f64 + 1.0);
-    let frequencies_mel =
-        Array1::from_iter((0..n_mel_bands + 2).map(|i| i as f64)) * delta_mel + min_freq_mel;
-    let frequencies_hz = frequencies_mel.map(|mel| mel_to_hertz(*mel));
+    // generate center frequencies and band edges for the filter bank
+    let frequencies_hz =
+        scale.band_edges(min_freq_hz as f64, max_freq_hz as f64, n_mel_bands as usize);
 
     // build the mel input scale and transformation matrix
     let mel_x = ndarray::Array1::linspace(0., mic_rate as f64 / 2.0, n_fft_bins as usize);
@@ -368,6 +494,35 @@ pub fn create_mel_bank(
     MelBank { x: mel_x, y: mel_y }
 }
 
+/// Resample `y` to `n` evenly spaced values by linear interpolation, keeping the first and last
+fn interpolate(y: &Array1<f64>, n: usize) -> Array1<f64> {
+    if y.len() < 2 || n < 2 {
+        return Array1::from_elem(n, y.first().copied().unwrap_or(0.0));
+    }
+    let spacing = (y.len() - 1) as f64 / (n - 1) as f64;
+    Array1::from_shape_fn(n, |i| {
+        let x = i as f64 * spacing;
+        let lower = (x.floor() as usize).min(y.len() - 2);
+        y[lower] + (x - lower as f64) * (y[lower + 1] - y[lower])
+    })
+}
+
+/// Fully saturated color of a pitch class, with the hues in circle of fifths order so that
+/// closely related keys get similar colors
+fn pitch_class_color(class: usize) -> Array1<f64> {
+    hue_color(((class * 7) % N_PITCH_CLASSES) as f64 / N_PITCH_CLASSES as f64)
+}
+
+/// Fully saturated rgb color from 0 to 1 of a hue from 0 to 1, going from red through green and
+/// blue back to red
+fn hue_color(hue: f64) -> Array1<f64> {
+    // hsv to rgb with full saturation and value
+    Array1::from_iter([5.0, 3.0, 1.0].map(|n: f64| {
+        let k = (n + 6.0 * hue) % 6.0;
+        1.0 - k.min(4.0 - k).clamp(0.0, 1.0)
+    }))
+}
+
 fn gaussian_kernel(sigma: f64, order: u32, radius: u32) -> Array1<f64> {
     let exponent_range: Array1<u32> = ndarray::ArrayBase::from_iter(0..order + 1);
     let order = order as usize;
@@ -375,7 +530,7 @@ fn gaussian_kernel(sigma: f64, order: u32, radius: u32) -> Array1<f64> {
     let sigma2 = sigma * sigma;
     let mut phi_x: Array1<f64> = x.map(|v| (-0.5 / sigma2 * v.pow(2) as f64).exp());
     let sum = phi_x.sum();
-    phi_x = phi_x / sum;
+    phi_x /= sum;
 
     if order == 0 {
         return phi_x;
@@ -474,10 +629,13 @@ mod test_dsp_functions {
 
     #[test]
     fn test_rfft() {
-        let mut config = Config::default();
-        config.n_fft_bins = 16;
+        let config = Config {
+            n_fft_bins: 16,
+            window: Window::None,
+            ..Default::default()
+        };
 
-        let dsp = Dsp::new(config);
+        let mut dsp = Dsp::new(config);
 
         let input1 = arr1(&[
             1.18550208,
@@ -525,17 +683,196 @@ mod test_dsp_functions {
         ]);
 
         let epsilon = 1e-3;
-        let output1 = dsp.exec_rfft(&input1);
+        let output1 = dsp.exec_rfft(input1).to_owned();
         assert_abs_diff_eq!(output1, expected1, epsilon = epsilon);
-        let output2 = dsp.exec_rfft(&input2);
+        let output2 = dsp.exec_rfft(input2).to_owned();
         assert_abs_diff_eq!(output2, expected2, epsilon = epsilon);
     }
 
+    #[test]
+    fn test_windowed_rfft() {
+        // reference spectra are np.abs(np.fft.fft(input * window))[:32] with the symmetric
+        // windows, e.g. np.hanning(64)
+        let mut npz_reader = NpzReader::new(File::open("./test/window_test.npz").unwrap()).unwrap();
+        let input: Array1<f64> = npz_reader.by_name("input.npy").unwrap();
+
+        for (window, name) in [
+            (Window::None, "none"),
+            (Window::Hann, "hann"),
+            (Window::Hamming, "hamming"),
+            (Window::BlackmanHarris, "blackman_harris"),
+        ] {
+            let coefficients: Array1<f64> =
+                npz_reader.by_name(&format!("window_{}.npy", name)).unwrap();
+            let expected: Array1<f64> = npz_reader
+                .by_name(&format!("expected_{}.npy", name))
+                .unwrap();
+            assert_abs_diff_eq!(window.coefficients(64), coefficients, epsilon = 1e-12);
+
+            let mut dsp = Dsp::new(Config {
+                n_fft_bins: 64,
+                n_mel_bands: 8,
+                window,
+                ..Default::default()
+            });
+            assert_abs_diff_eq!(
+                *dsp.exec_rfft(input.iter().copied()),
+                expected,
+                epsilon = 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn test_time_constants_follow_frame_rate() {
+        // the defaults reproduce the alphas the effects were tuned with at 60 fps
+        let config = Config::default();
+        for (time, (rise, decay)) in [
+            (config.mel_gain_time, (0.01, 0.99)),
+            (config.mel_smoothing_time, (0.5, 0.99)),
+            (config.scroll_gain_time, (0.2, 0.2)),
+            (config.power_pixels_time, (0.99, 0.1)),
+            (config.spectrum_blue_time, (0.1, 0.5)),
+        ] {
+            let (alpha_rise, alpha_decay) = time.alphas(60);
+            assert_abs_diff_eq!(alpha_rise, rise, epsilon = 1e-3);
+            assert_abs_diff_eq!(alpha_decay, decay, epsilon = 1e-3);
+        }
+
+        // a step takes the same time to settle at any frame rate
+        let step = Array1::ones(4);
+        let settle = |fps: u32| {
+            let mut filter = ExpFilterArr::<Ix1>::new(4, 0.0, TimeConstant::new(100.0, 0.0), fps);
+            for _ in 0..fps / 4 {
+                filter.update(&step);
+            }
+            filter.current[0]
+        };
+        assert_abs_diff_eq!(settle(40), settle(120), epsilon = 1e-12);
+        assert_abs_diff_eq!(settle(60), 1.0 - (-2.5f64).exp(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_gate_fades_out_and_holds_gain_in_silence() {
+        let config = Config {
+            min_volume_threshold: 1e-3,
+            gate_release_ms: 100,
+            ..Default::default()
+        };
+        let mut dsp = Dsp::new(config.clone());
+        let mut history = History::new(config.n_fft_bins as usize);
+        let hop = (config.mic_rate / config.fps) as usize;
+        let mut run = |dsp: &mut Dsp, amplitude: f32, frames| {
+            let samples: Vec<f32> = (0..hop)
+                .map(|i| amplitude * (i as f32 * 0.1).sin())
+                .collect();
+            for _ in 0..frames {
+                history.push(&samples);
+                dsp.analyze(&history);
+            }
+        };
+
+        run(&mut dsp, 0.5, 10);
+        assert_eq!(dsp.analysis().gate, 1.0);
+
+        // once the tone has left the history the gate closes and fades out over the release
+        run(&mut dsp, 1e-5, 4);
+        assert!(dsp.analysis().gate < 1.0);
+        let gain = dsp.mel_gain.current.clone();
+        run(&mut dsp, 1e-5, 10);
+        assert_eq!(dsp.analysis().gate, 0.0);
+        assert_eq!(dsp.mel_gain.current, gain);
+    }
+
+    #[test]
+    fn test_stereo_image() {
+        let config = Config {
+            channels: 2,
+            ..Default::default()
+        };
+        let hop = (config.mic_rate / config.fps) as usize;
+        // run interleaved audio with a left and a right tone through the dsp for 20 frames
+        let run = |left: f32, right: f32| {
+            let mut dsp = Dsp::new(config.clone());
+            let mut histories = [0, 1, 2].map(|_| History::new(config.n_fft_bins as usize));
+            let samples: Vec<f32> = (0..hop)
+                .flat_map(|i| {
+                    let tone = (i as f32 * 0.1).sin();
+                    [left * tone, right * tone]
+                })
+                .collect();
+            for _ in 0..20 {
+                histories[0].push_downmix(&samples, 2);
+                histories[1].push_channel(&samples, 2, 0);
+                histories[2].push_channel(&samples, 2, 1);
+                dsp.analyze(&histories[0]);
+                dsp.analyze_stereo(&histories[1], &histories[2]);
+            }
+            dsp
+        };
+
+        let stereo = run(0.5, 0.5).analysis().stereo.clone().unwrap();
+        assert_abs_diff_eq!(stereo.width, 0.0, epsilon = 1e-12);
+        let stereo = run(0.5, -0.5).analysis().stereo.clone().unwrap();
+        assert_abs_diff_eq!(stereo.width, 1.0, epsilon = 1e-12);
+
+        // a tone on the left only shows up in the left channel's spectrum
+        let stereo = run(0.5, 0.0).analysis().stereo.clone().unwrap();
+        assert_abs_diff_eq!(stereo.width, 0.5, epsilon = 1e-12);
+        let (left, right) = (stereo.mel[0].sum(), stereo.mel[1].sum());
+        assert!(left > 0.0);
+        assert!(right < 0.01 * left, "left {left}, right {right}");
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(
+            interpolate(&arr1(&[0.0, 1.0, 0.0]), 5),
+            arr1(&[0.0, 0.5, 1.0, 0.5, 0.0])
+        );
+        assert_eq!(interpolate(&arr1(&[2.0]), 3), arr1(&[2.0; 3]));
+    }
+
+    /// Per frame cost of pushing a hop of audio, analyzing it and rendering the default preset.
+    /// Run with `cargo test --release bench_frame_cost -- --ignored --nocapture`, e.g. on the Pi.
+    #[test]
+    #[ignore]
+    fn bench_frame_cost() {
+        let config = Config::default();
+        let mut dsp = Dsp::new(config.clone());
+        let mut history = History::new(config.n_fft_bins as usize);
+        let mut effect = config.preset.effect(&config);
+        let mut display_values = Array2::zeros((config.n_points as usize, 3));
+        let hop: Vec<f32> = (0..config.mic_rate / config.fps)
+            .map(|i| (i as f32 * 0.05).sin())
+            .collect();
+
+        let n_frames = 2000;
+        let start = std::time::Instant::now();
+        for _ in 0..n_frames {
+            history.push(&hop);
+            dsp.analyze(&history);
+            effect.render(dsp.analysis(), &mut display_values);
+        }
+        let per_frame = start.elapsed() / n_frames;
+        let budget = std::time::Duration::from_secs_f64(1.0 / config.fps as f64);
+        println!(
+            "{:?} per frame, {:.1}% of the {} fps budget",
+            per_frame,
+            100.0 * per_frame.as_secs_f64() / budget.as_secs_f64(),
+            config.fps
+        );
+        assert!(per_frame < budget);
+    }
+
     #[test]
     fn test_get_mel_repr() {
-        let mut config = Config::default();
-        config.n_fft_bins = 16;
-        config.n_mel_bands = 8;
+        // the mel bank spans the n_fft_bins / 2 magnitudes returned by exec_rfft
+        let config = Config {
+            n_fft_bins: 32,
+            n_mel_bands: 8,
+            ..Default::default()
+        };
         let dsp = Dsp::new(config);
 
         let input = arr1(&[
@@ -550,6 +887,44 @@ mod test_dsp_functions {
         assert_abs_diff_eq!(dsp.get_mel_repr(&input), expected, epsilon = 1e-5);
     }
 
+    #[test]
+    fn test_update_config_keeps_unchanged_state() {
+        let config = Config {
+            n_mel_bands: 16,
+            ..Default::default()
+        };
+        let mut dsp = Dsp::new(config.clone());
+        dsp.gain_and_smooth(&mut Array1::linspace(0.1, 1.6, 16));
+        let smoothed = dsp.mel_smoothing.current.clone();
+        let mel_bank = dsp.mel_bank.y.clone();
+
+        // unrelated parameters leave the filter bank and the running filter state alone
+        dsp.update_config(Config {
+            fps: 30,
+            device_port: 8888,
+            ..config.clone()
+        });
+        assert_eq!(dsp.mel_smoothing.current, smoothed);
+        assert_eq!(dsp.mel_bank.y, mel_bank);
+
+        // frequency range changes rebuild the filter bank but keep the band filters
+        dsp.update_config(Config {
+            max_freq_hz: 8000,
+            ..config.clone()
+        });
+        assert_eq!(dsp.mel_smoothing.current, smoothed);
+        assert_ne!(dsp.mel_bank.y, mel_bank);
+
+        // band count changes resize everything that holds one value per band
+        dsp.update_config(Config {
+            n_mel_bands: 8,
+            ..config
+        });
+        assert_eq!(dsp.mel_bank.y.shape(), &[8, 1024]);
+        assert_eq!(dsp.mel_smoothing.current.len(), 8);
+        dsp.gain_and_smooth(&mut Array1::linspace(0.1, 0.8, 8));
+    }
+
     #[test]
     fn test_correlate_1d() {
         let weights = arr1(&[1., 2., 3., 4.]);
@@ -664,7 +1039,7 @@ mod test_dsp_functions {
 
     #[test]
     fn test_create_mel_bank() {
-        let output = create_mel_bank(44100, 735, 24, 200, 12000);
+        let output = create_filter_bank(FrequencyScale::Mel, 44100, 735, 24, 200, 12000);
         let mut npz_reader = NpzReader::new(File::open("./test/mel_test.npz").unwrap()).unwrap();
 
         let expected = MelBank {
@@ -684,7 +1059,7 @@ mod test_display_funcs {
 
     use crate::config::Config;
 
-    use super::Dsp;
+    use super::{Dsp, Preset};
 
     const DISPLAY_BUFFER: [[f64; 3]; 100] = [
         [-1.08949971e+02, -5.30869048e+00, -1.74564589e+02],
@@ -798,13 +1173,19 @@ mod test_display_funcs {
     #[test]
     fn test_display_scroll() {
         let mut display_buffer = arr2(&DISPLAY_BUFFER);
-        let mut config = Config::default();
-        config.n_points = display_buffer.shape()[0] as u8;
-        config.n_mel_bands = 16;
+        let config = Config {
+            n_points: display_buffer.shape()[0] as u8,
+            n_mel_bands: 16,
+            ..Default::default()
+        };
 
-        let mut dsp = Dsp::new(config);
-        dsp.gain_and_smooth(&mut arr1(&MEL_UPDATE));
+        let mut dsp = Dsp::new(config.clone());
+        let mut mel = arr1(&MEL_UPDATE);
+        dsp.gain_and_smooth(&mut mel);
+        dsp.analysis.mel = mel;
 
-        dsp.apply_transform_inplace(super::Preset::Scroll, &mut display_buffer);
+        Preset::Scroll
+            .effect(&config)
+            .render(dsp.analysis(), &mut display_buffer);
     }
 }
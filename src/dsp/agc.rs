@@ -0,0 +1,98 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// How the gain of the mel spectrum is set
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GainMode {
+    /// Follow the level of the music towards agc_target, within agc_min_gain_db and
+    /// agc_max_gain_db
+    Auto,
+    /// Hold manual_gain_db whatever the level
+    Manual,
+}
+
+impl GainMode {
+    pub const ALL: [GainMode; 2] = [GainMode::Auto, GainMode::Manual];
+}
+
+impl Display for GainMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                GainMode::Auto => "Auto gain",
+                GainMode::Manual => "Manual gain",
+            }
+        )
+    }
+}
+
+/// The gain applied to each band of the mel power spectrum, from the level the band's gain
+/// filter tracks
+pub struct Agc {
+    mode: GainMode,
+    target: f64,
+    min_gain: f64,
+    max_gain: f64,
+    manual_gain: f64,
+}
+
+/// Power ratio of a gain in decibels
+fn db_to_power(db: f64) -> f64 {
+    10f64.powf(db / 10.0)
+}
+
+impl Agc {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            mode: config.gain_mode,
+            target: config.agc_target,
+            min_gain: db_to_power(config.agc_min_gain_db),
+            max_gain: db_to_power(config.agc_max_gain_db),
+            manual_gain: db_to_power(config.manual_gain_db),
+        }
+    }
+
+    /// Gain for a band at `level`. Limiting the gain keeps quiet passages from being lifted to
+    /// full brightness and stops a loud drop after them from clipping while the level catches up.
+    pub fn gain(&self, level: f64) -> f64 {
+        match self.mode {
+            GainMode::Auto => (self.target / level).clamp(self.min_gain, self.max_gain),
+            GainMode::Manual => self.manual_gain,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_gain_limits_and_manual_override() {
+        let mut config = Config {
+            agc_target: 0.5,
+            agc_min_gain_db: -30.0,
+            agc_max_gain_db: 20.0,
+            manual_gain_db: -10.0,
+            ..Default::default()
+        };
+        let agc = Agc::new(&config);
+        assert_abs_diff_eq!(agc.gain(0.1), 5.0, epsilon = 1e-12);
+        // a quiet level is lifted by no more than 20 dB, a loud one cut by no more than 30 dB
+        assert_abs_diff_eq!(agc.gain(1e-4), 100.0, epsilon = 1e-9);
+        assert_abs_diff_eq!(agc.gain(1e4), 1e-3, epsilon = 1e-12);
+
+        config.gain_mode = GainMode::Manual;
+        let agc = Agc::new(&config);
+        for level in [1e-4, 0.1, 1e4] {
+            assert_abs_diff_eq!(agc.gain(level), 0.1, epsilon = 1e-12);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use ndarray::{Array1, Array2};
+
+use super::scale::hertz_to_note;
+
+/// Number of pitch classes in an octave, starting from C
+pub const N_PITCH_CLASSES: usize = 12;
+
+/// Folds fft magnitudes onto the 12 pitch classes of the equal tempered scale, giving how much
+/// of each note, in any octave, is sounding
+pub struct ChromaFilter {
+    // one row per pitch class, one column per fft bin
+    weights: Array2<f64>,
+}
+
+impl ChromaFilter {
+    /// A filter for the `n_fft_bins / 2` magnitudes of an `n_fft_bins` point fft, using only the
+    /// bins between `min_freq_hz` and `max_freq_hz`
+    pub fn new(mic_rate: u32, n_fft_bins: u32, min_freq_hz: u32, max_freq_hz: u32) -> Self {
+        let bin_width = mic_rate as f64 / n_fft_bins as f64;
+        let mut weights = Array2::zeros((N_PITCH_CLASSES, (n_fft_bins / 2) as usize));
+        for (bin, mut column) in weights.columns_mut().into_iter().enumerate() {
+            let hertz = bin as f64 * bin_width;
+            if hertz < (min_freq_hz as f64).max(bin_width) || hertz > max_freq_hz as f64 {
+                continue;
+            }
+            // a low bin covers several semitones, so its energy is spread over their pitch classes
+            let width = 12.0 * ((hertz + bin_width / 2.0) / (hertz - bin_width / 2.0)).log2();
+            let spread = width.max(1.0) / 2.0;
+            // semitones above C, as A4 is 9 semitones above C4
+            let pitch = hertz_to_note(hertz) + 9.0;
+            column.assign(&Array1::from_shape_fn(N_PITCH_CLASSES, |class| {
+                let distance = (pitch - class as f64 + 6.0).rem_euclid(12.0) - 6.0;
+                (-0.5 * (distance / spread).powi(2)).exp()
+            }));
+            let total = column.sum();
+            column /= total;
+        }
+        Self { weights }
+    }
+
+    /// Energy of each pitch class in the spectrum `magnitudes`, scaled so the strongest is 1,
+    /// or all zeros for a silent spectrum
+    pub fn apply(&self, magnitudes: &Array1<f64>) -> Array1<f64> {
+        let mut chroma = self.weights.dot(&magnitudes.mapv(|x| x.powi(2)));
+        let max = chroma.fold(0.0, |a: f64, b| a.max(*b));
+        if max > 0.0 {
+            chroma /= max;
+        }
+        chroma
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use crate::{config::Config, dsp::Dsp};
+
+    use super::*;
+
+    fn chroma_of(hertz: &[f64]) -> Array1<f64> {
+        let config = Config::default();
+        let mut dsp = Dsp::new(config.clone());
+        let rate = config.mic_rate as f64;
+        let magnitudes = dsp.exec_rfft((0..config.n_fft_bins).map(|i| {
+            hertz
+                .iter()
+                .map(|f| (2.0 * PI * f * i as f64 / rate).sin())
+                .sum()
+        }));
+        ChromaFilter::new(
+            config.mic_rate,
+            config.n_fft_bins,
+            config.min_freq_hz,
+            config.max_freq_hz,
+        )
+        .apply(magnitudes)
+    }
+
+    /// Pitch classes from strongest to weakest
+    fn ranked(chroma: &Array1<f64>) -> Vec<usize> {
+        let mut classes: Vec<usize> = (0..N_PITCH_CLASSES).collect();
+        classes.sort_by(|a, b| chroma[*b].total_cmp(&chroma[*a]));
+        classes
+    }
+
+    #[test]
+    fn test_single_note() {
+        // A4 and A5 both belong to pitch class A
+        let chroma = chroma_of(&[440.0, 880.0]);
+        assert_eq!(ranked(&chroma)[0], 9);
+        assert_eq!(chroma[9], 1.0);
+        assert!(chroma.iter().enumerate().all(|(i, x)| i == 9 || *x < 0.5));
+    }
+
+    #[test]
+    fn test_c_major_triad() {
+        // C4, E4 and G4
+        let chroma = chroma_of(&[261.63, 329.63, 392.0]);
+        let mut top = ranked(&chroma)[..3].to_vec();
+        top.sort();
+        assert_eq!(top, vec![0, 4, 7]);
+    }
+
+    #[test]
+    fn test_silence() {
+        assert_eq!(chroma_of(&[]), Array1::zeros(N_PITCH_CLASSES));
+    }
+}
@@ -0,0 +1,796 @@
+//! Effects that render the analysis of the audio onto the strip.
+//!
+//! To add an effect:
+//!
+//! 1. implement [`Effect`] for a struct holding whatever the effect animates from frame to frame
+//!
+//! 2. create a preset enum and make the effect for it in [`Preset::effect`]
+//!
+//! 3. give any filters the effect smooths with a TimeConstant in Config, so that they behave the
+//!    same at any frame rate, and apply it in the effect's `update_config`
+//!
+//! Effects react to the latest frame's analysis, e.g. beats, through the [`Analysis`] they render.
+mod fire;
+mod ripple;
+
+use clap::ValueEnum;
+use ndarray::{s, Array1, Array2, Axis, Ix1, Ix2};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    correlate_1d, gaussian_kernel, hue_color, interpolate, pitch_class_color, Analysis,
+    ExpFilterArr, N_PITCH_CLASSES,
+};
+use crate::config::Config;
+pub use fire::Fire;
+pub use ripple::Ripples;
+
+/// Power the chroma is raised to by the chroma preset, the higher the more the strongest notes
+/// crowd out the rest
+const CHROMA_CONTRAST: i32 = 4;
+/// Pixels on either side of the center that the stereo preset uses to show the stereo width
+const STEREO_INDICATOR_PIXELS: usize = 2;
+/// Level at the top of the vu meter, relative to the running level the power gain tracks, so
+/// that loud passages have room to reach the red
+const VU_HEADROOM: f64 = 2.0;
+/// Fractions of the vu meter where the yellow and the red zones start
+const VU_YELLOW: f64 = 0.6;
+const VU_RED: f64 = 0.85;
+/// How far round the color wheel the strobe moves on each beat
+const STROBE_HUE_STEP: f64 = 0.15;
+
+/// Renders the analysis of each frame of audio onto the strip. An effect keeps whatever it
+/// animates between frames, so it should be given every frame in order.
+pub trait Effect: Send {
+    /// Draw the latest frame's `analysis` onto `pixels`, a row of rgb values from 0 to 255 for
+    /// each pixel of the strip
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>);
+
+    /// Move to a new config, keeping whatever running state still fits it
+    fn update_config(&mut self, config: &Config);
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    Scroll,
+    Power,
+    Spectrum,
+    Chroma,
+    Stereo,
+    Vu,
+    Fire,
+    Strobe,
+    Ripple,
+    Bars,
+}
+
+impl Preset {
+    /// A new effect of this preset for `config`
+    pub fn effect(&self, config: &Config) -> Box<dyn Effect> {
+        match self {
+            Preset::Scroll => Box::new(Scroll::new(config)),
+            Preset::Power => Box::new(Power::new(config)),
+            Preset::Spectrum => Box::new(Spectrum::new(config)),
+            Preset::Chroma => Box::new(Chroma::new()),
+            Preset::Stereo => Box::new(StereoSpectrum),
+            Preset::Vu => Box::new(Vu::new(config)),
+            Preset::Fire => Box::new(Fire::new(config)),
+            Preset::Strobe => Box::new(Strobe::new(config)),
+            Preset::Ripple => Box::new(Ripples::new(config)),
+            Preset::Bars => Box::new(Bars::new(config)),
+        }
+    }
+}
+
+/// Pixels in the half of the strip that mirrored effects draw, including the center pixel of a
+/// strip with an odd number of pixels
+fn half_pixels(n_points: usize) -> usize {
+    n_points - n_points / 2
+}
+
+/// Mirror `half`, which runs from the center outwards, onto both halves of `pixels`
+fn mirror(half: &Array2<f64>, pixels: &mut Array2<f64>) {
+    pixels.assign(&ndarray::concatenate![
+        Axis(0),
+        half.slice(s![pixels.nrows() % 2..;-1, ..]),
+        *half
+    ]);
+}
+
+/// Colors scrolling outwards from the center, with the bass, mids and treble in red, green and
+/// blue
+pub struct Scroll {
+    gain: ExpFilterArr<Ix1>,
+    kernel: Array1<f64>,
+}
+
+impl Scroll {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            gain: ExpFilterArr::<Ix1>::new(
+                config.n_mel_bands as usize,
+                0.01,
+                config.scroll_gain_time,
+                config.fps,
+            ),
+            kernel: gaussian_kernel(0.2, 0, 1), // TODO: determine whether radius 1 is what we want
+        }
+    }
+}
+
+impl Effect for Scroll {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let n_points = pixels.nrows();
+        let mut display_slice = pixels.slice(s![n_points / 2.., ..]).to_owned();
+        let mut y = analysis.mel.clone();
+        // y = y**2.0
+        y.map_inplace(|x| *x = x.powi(2));
+        // update gain
+        self.gain.update(&y);
+        // y /= gain.value
+        // y *= 255
+        y.zip_mut_with(&self.gain.current, |y, g| *y = 255.0 * (*y) / g);
+
+        // scrolling effect
+        // p[1:, :] = p[:-1, :]
+        // p *= 0.98
+        for i in 1..display_slice.shape()[0] - 1 {
+            let left_pixels = display_slice.slice(s![i - 1, ..]).to_owned() * 0.98;
+            display_slice.slice_mut(s![i, ..]).assign(&left_pixels);
+        }
+        // apply gaussian filter
+        let mut filter_display_buffer = correlate_1d(&display_slice, &self.kernel);
+
+        // create one new color originating at the center, brightened by onsets in its bands so
+        // kicks and snares stand out
+        let bands = &analysis.onset.bands;
+        for i in 0..3 {
+            let s = y.slice(s![i * y.shape()[0] / 3..(i + 1) * y.shape()[0] / 3]);
+            let mut max: f64 = 0.0;
+            s.map(|x| {
+                max = f64::max(max, *x);
+            });
+            let onset = bands
+                .slice(s![i * bands.len() / 3..(i + 1) * bands.len() / 3])
+                .fold(0.0, |a: f64, b| a.max(*b));
+
+            filter_display_buffer[[0, i]] = max * (1.0 + onset);
+        }
+
+        // scroll display
+        pixels.assign(&ndarray::concatenate![
+            Axis(0),
+            filter_display_buffer.slice(s![n_points % 2..,..;-1]),
+            filter_display_buffer,
+        ]);
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        if self.gain.current.len() != config.n_mel_bands as usize {
+            self.gain.resize(config.n_mel_bands as usize);
+        }
+        self.gain
+            .set_time_constant(config.scroll_gain_time, config.fps);
+    }
+}
+
+/// Bars growing out from the center with the energy of the bass, mids and treble, in red, green
+/// and blue
+pub struct Power {
+    gain: ExpFilterArr<Ix1>,
+    pixels: ExpFilterArr<Ix2>,
+    kernel: Array1<f64>,
+}
+
+impl Power {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            gain: ExpFilterArr::<Ix1>::new(
+                config.n_mel_bands as usize,
+                0.01,
+                config.power_gain_time,
+                config.fps,
+            ),
+            pixels: ExpFilterArr::<Ix2>::new(
+                half_pixels(config.n_points as usize),
+                1.,
+                config.power_pixels_time,
+                config.fps,
+            ),
+            kernel: gaussian_kernel(0.4, 0, 1), // TODO: determine whether radius 1 is what we want
+        }
+    }
+}
+
+impl Effect for Power {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let n_points = pixels.nrows();
+        let mut y = analysis.mel.clone();
+        self.gain.update(&y);
+        let mut display_slice = pixels.slice(s![n_points / 2.., ..]).to_owned();
+
+        // y /= gain.value
+        // y *= float(config.n_pixels // 2) - 1)
+        y.zip_mut_with(&self.gain.current, |y, g| {
+            *y *= (n_points / 2 - 1) as f64 / g;
+        });
+
+        // map color channels according to energy in different frequency bands
+        let scale = 0.9;
+        for i in 0..3 {
+            let s = y
+                .slice(s![i * y.shape()[0] / 3..(i + 1) * y.shape()[0] / 3])
+                .map(|x| x.powf(scale));
+            // numpy clamped bars past the end of the strip, so do the same
+            let mean = (s.mean().unwrap() as usize).min(display_slice.shape()[0]);
+            display_slice.slice_mut(s![..mean, i]).fill(255.0);
+            display_slice.slice_mut(s![mean.., i]).fill(0.0);
+        }
+
+        self.pixels.update(&display_slice);
+        display_slice.map_inplace(|x| {
+            *x = x.round();
+        });
+        display_slice.assign(&correlate_1d(&display_slice, &self.kernel));
+
+        pixels.assign(&ndarray::concatenate![
+            Axis(0),
+            display_slice.slice(s![n_points % 2..,..;-1]),
+            display_slice
+        ]);
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        if self.gain.current.len() != config.n_mel_bands as usize {
+            self.gain.resize(config.n_mel_bands as usize);
+        }
+        let n_pixels = half_pixels(config.n_points as usize);
+        if self.pixels.current.nrows() != n_pixels {
+            self.pixels.resize(n_pixels);
+        }
+        self.gain
+            .set_time_constant(config.power_gain_time, config.fps);
+        self.pixels
+            .set_time_constant(config.power_pixels_time, config.fps);
+    }
+}
+
+/// The mel spectrum spread across each half of the strip, red where it rises above its running
+/// average, green where it changes and blue with its level
+pub struct Spectrum {
+    common_mode: ExpFilterArr<Ix1>,
+    r_filt: ExpFilterArr<Ix1>,
+    b_filt: ExpFilterArr<Ix1>,
+    prev_spectrum: Array1<f64>,
+}
+
+impl Spectrum {
+    pub fn new(config: &Config) -> Self {
+        let n_pixels = half_pixels(config.n_points as usize);
+        Self {
+            common_mode: ExpFilterArr::<Ix1>::new(
+                n_pixels,
+                0.01,
+                config.spectrum_common_mode_time,
+                config.fps,
+            ),
+            r_filt: ExpFilterArr::<Ix1>::new(n_pixels, 0.01, config.spectrum_red_time, config.fps),
+            b_filt: ExpFilterArr::<Ix1>::new(n_pixels, 0.01, config.spectrum_blue_time, config.fps),
+            prev_spectrum: Array1::zeros(n_pixels),
+        }
+    }
+}
+
+impl Effect for Spectrum {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        // one value per pixel of the half strip
+        let y = interpolate(&analysis.mel, self.prev_spectrum.len());
+        self.common_mode.update(&y);
+        //diff = y - self.prev_spectrum
+        let diff = &y - &self.prev_spectrum;
+        self.prev_spectrum.assign(&y);
+
+        // color channel mappings
+        self.r_filt.update(&(&y - &self.common_mode.current));
+        let r = &self.r_filt.current;
+        let g = diff.map(|x| x.abs());
+        self.b_filt.update(&y);
+        let b = &self.b_filt.current;
+
+        // Mirror the color channels for symmetric output
+        mirror(&(ndarray::stack![Axis(1), *r, g, *b] * 255.0), pixels);
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        let n_pixels = half_pixels(config.n_points as usize);
+        if self.prev_spectrum.len() != n_pixels {
+            self.common_mode.resize(n_pixels);
+            self.r_filt.resize(n_pixels);
+            self.b_filt.resize(n_pixels);
+            self.prev_spectrum = Array1::zeros(n_pixels);
+        }
+        let fps = config.fps;
+        self.common_mode
+            .set_time_constant(config.spectrum_common_mode_time, fps);
+        self.r_filt.set_time_constant(config.spectrum_red_time, fps);
+        self.b_filt
+            .set_time_constant(config.spectrum_blue_time, fps);
+    }
+}
+
+/// A segment for each pitch class sized by its strength, strongest in the center, colored round
+/// the circle of fifths
+pub struct Chroma {
+    kernel: Array1<f64>,
+}
+
+impl Chroma {
+    pub fn new() -> Self {
+        Self {
+            kernel: gaussian_kernel(0.4, 0, 1),
+        }
+    }
+}
+
+impl Default for Chroma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for Chroma {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let n_pixels = half_pixels(pixels.nrows());
+        let mut display_slice = Array2::zeros((n_pixels, 3));
+
+        // sharpen the chroma so the notes of the chord take up the strip, not the background
+        let weights = analysis.chroma.mapv(|x| x.powi(CHROMA_CONTRAST));
+        let total = weights.sum();
+        let brightness = 255.0 * analysis.mel.fold(0.0, |a: f64, b| a.max(*b)).min(1.0);
+
+        // a segment for each pitch class sized by its strength, strongest in the center
+        if total > 0.0 {
+            let mut classes: Vec<usize> = (0..N_PITCH_CLASSES).collect();
+            classes.sort_by(|a, b| weights[*b].total_cmp(&weights[*a]));
+            let mut start = 0.0;
+            for class in classes {
+                let end = start + weights[class] / total * n_pixels as f64;
+                let color = pitch_class_color(class) * brightness;
+                display_slice
+                    .slice_mut(s![
+                        start.round() as usize..(end.round() as usize).min(n_pixels),
+                        ..
+                    ])
+                    .assign(&color);
+                start = end;
+            }
+        }
+        display_slice.assign(&correlate_1d(&display_slice, &self.kernel));
+
+        mirror(&display_slice, pixels);
+    }
+
+    fn update_config(&mut self, _config: &Config) {}
+}
+
+/// Each half of the strip shows its own channel's mel spectrum, with the stereo width in the
+/// center. Mono input shows the same on both sides.
+pub struct StereoSpectrum;
+
+impl Effect for StereoSpectrum {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let n_points = pixels.nrows();
+        let (mel, width) = match &analysis.stereo {
+            Some(stereo) => (stereo.mel.clone(), stereo.width),
+            // mono input shows the same on both sides
+            None => ([analysis.mel.clone(), analysis.mel.clone()], 0.0),
+        };
+
+        // each half runs from the center outwards through its channel's mel bands, colored
+        // from red for the bass to blue for the treble
+        let half = |mel: &Array1<f64>, n_pixels: usize| {
+            Array2::from_shape_fn((n_pixels, 3), |(pixel, color)| {
+                let band = pixel * mel.len() / n_pixels;
+                let hue = band as f64 / mel.len() as f64 * 2.0 / 3.0;
+                255.0 * mel[band].clamp(0.0, 1.0) * hue_color(hue)[color]
+            })
+        };
+        let mut left = half(&mel[0], n_points / 2);
+        let mut right = half(&mel[1], n_points - n_points / 2);
+
+        // the center shows the mid/side balance, from green for mono to red for wide
+        let indicator = ndarray::arr1(&[255.0 * width, 255.0 * (1.0 - width), 0.0]);
+        for side in [&mut left, &mut right] {
+            let n_indicator = STEREO_INDICATOR_PIXELS.min(side.shape()[0]);
+            side.slice_mut(s![..n_indicator, ..])
+                .assign(&indicator.broadcast((n_indicator, 3)).unwrap());
+        }
+
+        pixels.assign(&ndarray::concatenate![
+            Axis(0),
+            left.slice(s![..;-1, ..]),
+            right
+        ]);
+    }
+
+    fn update_config(&mut self, _config: &Config) {}
+}
+
+/// A vu meter of the rms level growing out from the center, with a marker holding the recent
+/// peak
+pub struct Vu {
+    gain: ExpFilterArr<Ix1>,
+    peak: ExpFilterArr<Ix1>,
+}
+
+impl Vu {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            gain: ExpFilterArr::<Ix1>::new(
+                config.n_mel_bands as usize,
+                0.01,
+                config.power_gain_time,
+                config.fps,
+            ),
+            peak: ExpFilterArr::<Ix1>::new(1, 0.0, config.vu_peak_time, config.fps),
+        }
+    }
+}
+
+impl Effect for Vu {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let y = &analysis.mel;
+        self.gain.update(y);
+        let n_pixels = half_pixels(pixels.nrows());
+
+        // rms of the bands relative to the running level, held at its peak and decaying slowly
+        let rms = (y / &self.gain.current)
+            .mapv(|x| x.powi(2))
+            .mean()
+            .unwrap_or(0.0)
+            .sqrt();
+        self.peak.update(&ndarray::arr1(&[rms]));
+        let fraction = |level: f64| (level / VU_HEADROOM).clamp(0.0, 1.0);
+        let lit = (fraction(rms) * n_pixels as f64).round() as usize;
+        let peak = (fraction(self.peak.current[0]) * (n_pixels - 1) as f64).round() as usize;
+
+        // a bar growing out from the center, green then yellow then red towards the ends
+        let mut display_slice = Array2::from_shape_fn((n_pixels, 3), |(pixel, color)| {
+            let position = (pixel as f64 + 0.5) / n_pixels as f64;
+            let zone = if position < VU_YELLOW {
+                [0.0, 255.0, 0.0]
+            } else if position < VU_RED {
+                [255.0, 255.0, 0.0]
+            } else {
+                [255.0, 0.0, 0.0]
+            };
+            if pixel < lit {
+                zone[color]
+            } else {
+                0.0
+            }
+        });
+        // the peak marker shows in white over the bar
+        if self.peak.current[0] > 0.0 {
+            display_slice.slice_mut(s![peak, ..]).fill(255.0);
+        }
+
+        mirror(&display_slice, pixels);
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        if self.gain.current.len() != config.n_mel_bands as usize {
+            self.gain.resize(config.n_mel_bands as usize);
+        }
+        self.gain
+            .set_time_constant(config.power_gain_time, config.fps);
+        self.peak.set_time_constant(config.vu_peak_time, config.fps);
+    }
+}
+
+/// Flashes the whole strip on every beat, in a new color each time. How often the strip may
+/// flash is left to the output, which holds back flashes for any effect.
+pub struct Strobe {
+    // brightness of the flash, which jumps to 1 on a beat
+    flash: ExpFilterArr<Ix1>,
+    hue: f64,
+}
+
+impl Strobe {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            flash: ExpFilterArr::<Ix1>::new(1, 0.0, config.strobe_time, config.fps),
+            hue: 0.0,
+        }
+    }
+}
+
+impl Effect for Strobe {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let beat = analysis.onset.beat;
+        if beat {
+            self.hue = (self.hue + STROBE_HUE_STEP) % 1.0;
+        }
+        self.flash
+            .update(&ndarray::arr1(&[if beat { 1.0 } else { 0.0 }]));
+
+        let color = hue_color(self.hue) * 255.0 * self.flash.current[0];
+        pixels.assign(&color.broadcast(pixels.dim()).unwrap());
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        self.flash.set_time_constant(config.strobe_time, config.fps);
+    }
+}
+
+/// The mel bands laid out along the strip like a spectrum analyzer, from the bass at the start
+/// to the treble at the end, with a white dot at each band holding its recent peak
+pub struct Bars {
+    peak: ExpFilterArr<Ix1>,
+}
+
+impl Bars {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            peak: ExpFilterArr::<Ix1>::new(
+                config.n_mel_bands as usize,
+                0.0,
+                config.bars_peak_time,
+                config.fps,
+            ),
+        }
+    }
+}
+
+impl Effect for Bars {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let n_points = pixels.nrows();
+        let mel = analysis.mel.mapv(|x| x.clamp(0.0, 1.0));
+        self.peak.update(&mel);
+        // pixels between the centers of neighbouring bands
+        let spacing = (n_points - 1) as f64 / (mel.len() - 1) as f64;
+
+        // energy interpolated between the bands on either side of each pixel
+        let levels = interpolate(&mel, n_points);
+        for (pixel, mut rgb) in pixels.rows_mut().into_iter().enumerate() {
+            let hue = pixel as f64 / n_points as f64 * 2.0 / 3.0;
+            rgb.assign(&(hue_color(hue) * 255.0 * levels[pixel]));
+        }
+        for (band, peak) in self.peak.current.iter().enumerate() {
+            let mut dot = pixels.row_mut((band as f64 * spacing).round() as usize);
+            dot.mapv_inplace(|x| x.max(255.0 * peak));
+        }
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        if self.peak.current.len() != config.n_mel_bands as usize {
+            self.peak.resize(config.n_mel_bands as usize);
+        }
+        self.peak
+            .set_time_constant(config.bars_peak_time, config.fps);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::assert_abs_diff_eq;
+    use ndarray::arr1;
+
+    use super::*;
+    use crate::dsp::Stereo;
+
+    fn pixels(config: &Config) -> Array2<f64> {
+        Array2::zeros((config.n_points as usize, 3))
+    }
+
+    #[test]
+    fn test_stereo_shows_each_channel_on_its_own_half() {
+        let config = Config::default();
+        let mut analysis = Analysis::new(config.n_mel_bands as usize);
+        // a tone on the left only lights up the left half
+        analysis.stereo = Some(Stereo {
+            mel: [
+                Array1::linspace(0.1, 1.0, config.n_mel_bands as usize),
+                Array1::zeros(config.n_mel_bands as usize),
+            ],
+            width: 0.5,
+        });
+        let mut display_values = pixels(&config);
+        Preset::Stereo
+            .effect(&config)
+            .render(&analysis, &mut display_values);
+        let center = config.n_points as usize / 2;
+        let left = display_values
+            .slice(s![..center - STEREO_INDICATOR_PIXELS, ..])
+            .sum();
+        let right = display_values
+            .slice(s![center + STEREO_INDICATOR_PIXELS.., ..])
+            .sum();
+        assert!(left > 0.0);
+        assert_eq!(right, 0.0);
+    }
+
+    #[test]
+    fn test_vu_meter_holds_the_peak() {
+        let config = Config {
+            n_points: 20,
+            ..Default::default()
+        };
+        let mut analysis = Analysis::new(config.n_mel_bands as usize);
+        let mut vu = Preset::Vu.effect(&config);
+        let mut display_values = pixels(&config);
+
+        // a burst far above the running level fills the meter into the red
+        analysis.mel.fill(1.0);
+        vu.render(&analysis, &mut display_values);
+        assert_eq!(display_values, display_values.slice(s![..;-1, ..]));
+        assert_eq!(display_values.row(10), arr1(&[0.0, 255.0, 0.0]));
+        assert_eq!(display_values.row(16), arr1(&[255.0, 255.0, 0.0]));
+        assert_eq!(display_values.row(18), arr1(&[255.0, 0.0, 0.0]));
+        assert_eq!(display_values.row(19), arr1(&[255.0, 255.0, 255.0]));
+
+        // in silence the bar goes out at once while the peak marker falls back slowly
+        analysis.mel.fill(0.0);
+        for _ in 0..config.fps * 2 {
+            vu.render(&analysis, &mut display_values);
+        }
+        let lit: Vec<usize> = (10..20)
+            .filter(|pixel| display_values.row(*pixel).sum() > 0.0)
+            .collect();
+        assert_eq!(lit.len(), 1);
+        assert!(lit[0] > 10 && lit[0] < 19, "peak marker at {}", lit[0]);
+        assert_eq!(display_values.row(lit[0]), arr1(&[255.0; 3]));
+    }
+
+    #[test]
+    fn test_chroma_mirrors_around_the_strongest_class() {
+        for n_points in [20, 21] {
+            let config = Config {
+                n_points,
+                ..Default::default()
+            };
+            let n = n_points as usize;
+            let mut analysis = Analysis::new(config.n_mel_bands as usize);
+            let mut display_values = pixels(&config);
+            analysis.mel.fill(1.0);
+            analysis.chroma = Array1::linspace(0.1, 1.0, N_PITCH_CLASSES);
+            Preset::Chroma
+                .effect(&config)
+                .render(&analysis, &mut display_values);
+
+            for i in 0..n {
+                for channel in 0..3 {
+                    assert_eq!(
+                        display_values[[i, channel]],
+                        display_values[[n - 1 - i, channel]],
+                        "pixel {} channel {} of {}",
+                        i,
+                        channel,
+                        n
+                    );
+                }
+            }
+            // the strongest pitch class is in the center, not at the ends
+            let strongest = pitch_class_color(N_PITCH_CLASSES - 1) * 255.0;
+            let center = display_values.row(n / 2).to_owned();
+            let edge = display_values.row(0).to_owned();
+            assert!(
+                (&center - &strongest).mapv(f64::abs).sum()
+                    < (&edge - &strongest).mapv(f64::abs).sum()
+            );
+        }
+    }
+
+    #[test]
+    fn test_strobe_flashes_a_new_color_on_each_beat() {
+        let config = Config::default();
+        let mut analysis = Analysis::new(config.n_mel_bands as usize);
+        let mut strobe = Preset::Strobe.effect(&config);
+        let mut display_values = pixels(&config);
+        let mut flash = |beat| {
+            analysis.onset.beat = beat;
+            strobe.render(&analysis, &mut display_values);
+            display_values.clone()
+        };
+
+        let first = flash(true);
+        assert!(first.rows().into_iter().all(|row| row == first.row(0)));
+        assert_abs_diff_eq!(first.row(0).fold(0.0, |a: f64, b| a.max(*b)), 255.0);
+        // the flash dies away between beats
+        let after = (0..config.fps / 3).map(|_| flash(false)).last().unwrap();
+        assert!(after.sum() < 0.01 * first.sum());
+        let second = flash(true);
+        assert_ne!(first.row(0), second.row(0));
+    }
+
+    #[test]
+    fn test_bars_lay_bands_along_the_strip_and_hold_peaks() {
+        let config = Config {
+            n_points: 31,
+            n_mel_bands: 4,
+            ..Default::default()
+        };
+        let mut analysis = Analysis::new(config.n_mel_bands as usize);
+        let mut bars = Preset::Bars.effect(&config);
+        let mut display_values = pixels(&config);
+
+        // the bands land at pixels 0, 10, 20 and 30, with the energy interpolated between them
+        analysis.mel = arr1(&[0.0, 0.0, 1.0, 0.0]);
+        bars.render(&analysis, &mut display_values);
+        let brightness = display_values.sum_axis(Axis(1));
+        assert_eq!(brightness[5], 0.0);
+        assert!(brightness[15] > 0.0 && brightness[15] < brightness[20]);
+        assert!(brightness[25] > 0.0 && brightness[30] == 0.0);
+
+        // once the band goes quiet its peak dot fades out slowly in white
+        analysis.mel.fill(0.0);
+        for _ in 0..config.fps / 10 {
+            bars.render(&analysis, &mut display_values);
+        }
+        let dot = display_values.row(20);
+        assert!(dot[0] > 100.0 && dot.iter().all(|x| *x == dot[0]));
+        assert_eq!(display_values.sum(), 3.0 * dot[0]);
+    }
+
+    #[test]
+    fn test_spectrum_fills_the_strip() {
+        for n_points in [255, 60] {
+            let config = Config {
+                n_points,
+                ..Default::default()
+            };
+            let mut analysis = Analysis::new(config.n_mel_bands as usize);
+            let mut display_values = pixels(&config);
+            analysis.mel = Array1::linspace(0.0, 1.0, config.n_mel_bands as usize);
+            Preset::Spectrum
+                .effect(&config)
+                .render(&analysis, &mut display_values);
+            assert_eq!(display_values, display_values.slice(s![..;-1, ..]));
+            assert!(display_values.column(2).sum() > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_every_preset_renders() {
+        for n_points in [255, 60] {
+            let config = Config {
+                n_points,
+                ..Default::default()
+            };
+            let mut analysis = Analysis::new(config.n_mel_bands as usize);
+            let mut display_values = pixels(&config);
+            analysis.mel = Array1::linspace(0.0, 1.0, config.n_mel_bands as usize);
+            for preset in Preset::value_variants() {
+                preset
+                    .effect(&config)
+                    .render(&analysis, &mut display_values);
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_preset_follows_config_changes() {
+        let config = Config::default();
+        let changed = Config {
+            n_points: 61,
+            n_mel_bands: 12,
+            fps: 30,
+            ..Default::default()
+        };
+        for preset in Preset::value_variants() {
+            let mut effect = preset.effect(&config);
+            let mut analysis = Analysis::new(config.n_mel_bands as usize);
+            analysis.mel.fill(0.5);
+            effect.render(&analysis, &mut pixels(&config));
+
+            effect.update_config(&changed);
+            let mut analysis = Analysis::new(changed.n_mel_bands as usize);
+            analysis.mel.fill(0.5);
+            analysis.onset.beat = true;
+            let mut display_values = pixels(&changed);
+            effect.render(&analysis, &mut display_values);
+            assert_eq!(display_values.nrows(), 61, "{:?}", preset);
+        }
+    }
+}
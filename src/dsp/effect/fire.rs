@@ -0,0 +1,130 @@
+use ndarray::{s, Array1, Array2};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{half_pixels, mirror, Effect};
+use crate::{config::Config, dsp::Analysis};
+
+/// Heat lost by every cell each second, at most, with no bass. Bass lowers it so the flames
+/// reach further along the strip.
+const COOLING_PER_SECOND: f64 = 30.0;
+/// Sparks lit each second at full bass
+const SPARKS_PER_SECOND: f64 = 40.0;
+/// Fraction of the strip near the base of the flames that sparks are lit in
+const SPARK_ZONE: f64 = 0.1;
+
+/// Flames simulated as heat diffusing along a row of cells, lit by random sparks at the base and
+/// cooling as they rise, after the Fire2012 effect. The bass fans the flames, which rise from the
+/// center of the strip outwards.
+pub struct Fire {
+    // from 0 for cold to 1 for white hot, with the base of the flames at index 0
+    heat: Array1<f64>,
+    rng: StdRng,
+    fps: u32,
+}
+
+impl Fire {
+    pub fn new(config: &Config) -> Self {
+        Self::with_cells(half_pixels(config.n_points as usize), config.fps)
+    }
+
+    fn with_cells(n_cells: usize, fps: u32) -> Self {
+        Self {
+            heat: Array1::zeros(n_cells),
+            rng: StdRng::from_entropy(),
+            fps,
+        }
+    }
+
+    /// Advance the flames by a frame, with `intensity` from 0 to 1 setting how often sparks are
+    /// lit, how hot they burn and how slowly the flames cool
+    fn update(&mut self, intensity: f64) {
+        let fps = self.fps;
+        let n_cells = self.heat.len();
+        let intensity = intensity.clamp(0.0, 1.0);
+
+        // every cell cools a little, by a random amount
+        let cooling = COOLING_PER_SECOND * (1.0 - 0.7 * intensity) / fps as f64;
+        for heat in self.heat.iter_mut() {
+            *heat = (*heat - self.rng.gen::<f64>() * cooling).max(0.0);
+        }
+
+        // heat drifts away from the base and diffuses
+        for i in (2..n_cells).rev() {
+            self.heat[i] = (self.heat[i - 1] + 2.0 * self.heat[i - 2]) / 3.0;
+        }
+
+        // new sparks near the base, on average as many a second as the intensity calls for
+        let mut sparks = SPARKS_PER_SECOND * intensity / fps as f64;
+        let spark_zone = ((n_cells as f64 * SPARK_ZONE).ceil() as usize).clamp(1, n_cells.max(1));
+        while n_cells > 0 && self.rng.gen::<f64>() < sparks {
+            let cell = self.rng.gen_range(0..spark_zone);
+            self.heat[cell] = (self.heat[cell] + self.rng.gen_range(0.6..1.0) * intensity).min(1.0);
+            sparks -= 1.0;
+        }
+    }
+
+    /// The color of each cell from 0 to 255, going from black through red and yellow to white as
+    /// the cell heats up
+    fn colors(&self) -> Array2<f64> {
+        Array2::from_shape_fn((self.heat.len(), 3), |(cell, color)| {
+            255.0 * (3.0 * self.heat[cell] - color as f64).clamp(0.0, 1.0)
+        })
+    }
+}
+
+impl Effect for Fire {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        // the bass fans the flames
+        let mel = &analysis.mel;
+        let bass = mel
+            .slice(s![..(mel.len() / 3).max(1)])
+            .mean()
+            .unwrap_or(0.0);
+        self.update(bass);
+        mirror(&self.colors(), pixels);
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        let n_cells = half_pixels(config.n_points as usize);
+        if self.heat.len() != n_cells {
+            // put the flames out
+            self.heat = Array1::zeros(n_cells);
+        }
+        self.fps = config.fps;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fire_burns_with_bass_and_dies_down_without() {
+        let fps = 60;
+        let mut fire = Fire::with_cells(50, fps);
+        fire.rng = StdRng::seed_from_u64(47);
+        for _ in 0..fps {
+            fire.update(1.0);
+        }
+        let colors = fire.colors();
+        // the base burns hot while the far end stays dark
+        assert!(colors.row(0).sum() > 255.0, "base {}", colors.row(0));
+        assert!(colors.row(49).sum() < colors.row(0).sum());
+        assert!(colors.iter().all(|x| (0.0..=255.0).contains(x)));
+
+        for _ in 0..fps * 5 {
+            fire.update(0.0);
+        }
+        assert_eq!(fire.colors().sum(), 0.0);
+    }
+
+    #[test]
+    fn test_heat_colors() {
+        let mut fire = Fire::with_cells(3, 60);
+        fire.heat = ndarray::arr1(&[0.0, 0.5, 1.0]);
+        assert_eq!(
+            fire.colors(),
+            ndarray::arr2(&[[0.0, 0.0, 0.0], [255.0, 127.5, 0.0], [255.0, 255.0, 255.0]])
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use ndarray::{Array1, Array2};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::Effect;
+use crate::{
+    config::Config,
+    dsp::{hue_color, Analysis},
+};
+
+/// How fast a ripple spreads, in lengths of the strip a second
+const SPEED: f64 = 0.5;
+/// Seconds from a ripple's start until it has faded out
+const LIFETIME: f64 = 1.2;
+/// Width of a ripple's wavefront in pixels
+const WIDTH: f64 = 1.5;
+/// Most ripples on the strip at once, the oldest making way for new ones
+const MAX_RIPPLES: usize = 24;
+
+struct Ripple {
+    // pixel the ripple started from
+    center: f64,
+    // seconds since it started
+    age: f64,
+    // rgb from 0 to 255 at the start
+    color: Array1<f64>,
+}
+
+/// Ripples that start at random pixels and spread outward in both directions, fading as they go
+/// and adding up where they cross. One starts on each onset, colored from red for the bass to
+/// blue for the treble by the band with the strongest onset.
+pub struct Ripples {
+    ripples: Vec<Ripple>,
+    rng: StdRng,
+    fps: u32,
+}
+
+impl Ripples {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            ripples: Vec::new(),
+            rng: StdRng::from_entropy(),
+            fps: config.fps,
+        }
+    }
+
+    /// Start a ripple of `color` from a random pixel of a strip with `n_pixels`
+    fn spawn(&mut self, n_pixels: usize, color: Array1<f64>) {
+        let center = self.rng.gen_range(0.0..n_pixels as f64);
+        self.spawn_at(center, color);
+    }
+
+    fn spawn_at(&mut self, center: f64, color: Array1<f64>) {
+        if self.ripples.len() == MAX_RIPPLES {
+            self.ripples.remove(0);
+        }
+        self.ripples.push(Ripple {
+            center,
+            age: 0.0,
+            color,
+        });
+    }
+
+    /// Move every ripple on by `seconds`, dropping those that have faded out
+    fn update(&mut self, seconds: f64) {
+        for ripple in self.ripples.iter_mut() {
+            ripple.age += seconds;
+        }
+        self.ripples.retain(|ripple| ripple.age < LIFETIME);
+    }
+
+    /// Draw the ripples onto a strip with `n_pixels`
+    fn draw(&self, n_pixels: usize) -> Array2<f64> {
+        let mut pixels = Array2::zeros((n_pixels, 3));
+        for ripple in &self.ripples {
+            let radius = ripple.age * SPEED * n_pixels as f64;
+            let fade = 1.0 - ripple.age / LIFETIME;
+            for (pixel, mut rgb) in pixels.rows_mut().into_iter().enumerate() {
+                let distance = (pixel as f64 - ripple.center).abs() - radius;
+                let front = (-0.5 * (distance / WIDTH).powi(2)).exp();
+                rgb.scaled_add(fade * front, &ripple.color);
+            }
+        }
+        pixels.mapv_inplace(|x| x.min(255.0));
+        pixels
+    }
+}
+
+impl Effect for Ripples {
+    fn render(&mut self, analysis: &Analysis, pixels: &mut Array2<f64>) {
+        let n_points = pixels.nrows();
+        self.update(1.0 / self.fps as f64);
+
+        let bands = &analysis.onset.bands;
+        let (band, strength) =
+            bands.iter().enumerate().fold(
+                (0, 0.0),
+                |(a, x), (b, y)| if *y > x { (b, *y) } else { (a, x) },
+            );
+        if strength > 0.0 {
+            let hue = band as f64 / bands.len() as f64 * 2.0 / 3.0;
+            let brightness = 255.0 * (0.5 + 0.5 * strength.min(1.0));
+            self.spawn(n_points, hue_color(hue) * brightness);
+        }
+
+        pixels.assign(&self.draw(n_points));
+    }
+
+    fn update_config(&mut self, config: &Config) {
+        self.fps = config.fps;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ripples_spread_fade_and_add_up() {
+        let n_pixels = 100;
+        let red = ndarray::arr1(&[200.0, 0.0, 0.0]);
+        let mut ripples = Ripples::new(&Config::default());
+        ripples.spawn_at(30.0, red.clone());
+        assert_eq!(ripples.draw(n_pixels).row(30), red);
+
+        // a fifth of a second later the wavefronts are a tenth of the strip out on either side
+        ripples.update(0.2);
+        let pixels = ripples.draw(n_pixels);
+        assert_eq!(pixels.row(20), pixels.row(40));
+        assert!(pixels[[20, 0]] > 100.0 && pixels[[20, 0]] < 200.0);
+        assert!(pixels[[30, 0]] < 1.0);
+
+        // a second ripple adds to the first where they cross
+        ripples.spawn_at(40.0, ndarray::arr1(&[0.0, 0.0, 200.0]));
+        let pixels = ripples.draw(n_pixels);
+        assert!(pixels[[40, 0]] > 100.0 && pixels[[40, 2]] == 200.0);
+
+        ripples.update(LIFETIME);
+        assert_eq!(ripples.ripples.len(), 0);
+        assert_eq!(ripples.draw(n_pixels).sum(), 0.0);
+    }
+}
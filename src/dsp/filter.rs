@@ -0,0 +1,190 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Q of the high-pass filter, the flattest passband without ringing
+const HIGHPASS_Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+/// A peaking band of the input eq, e.g. `{ freq_hz = 80, gain_db = -6, q = 1 }`
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct EqBand {
+    /// Center frequency of the band
+    pub freq_hz: f64,
+    /// Boost, or cut if negative, at the center frequency
+    pub gain_db: f64,
+    /// Sharpness of the band, higher is narrower
+    pub q: f64,
+}
+
+impl EqBand {
+    /// Describe what's wrong with the band for a signal sampled at `mic_rate`, if anything
+    pub fn check(&self, mic_rate: u32) -> Option<String> {
+        let nyquist = mic_rate as f64 / 2.0;
+        if !(self.freq_hz > 0.0 && self.freq_hz < nyquist) {
+            Some(format!(
+                "freq_hz must be between 0 and the nyquist frequency of mic_rate / 2, got {}",
+                self.freq_hz
+            ))
+        } else if !self.gain_db.is_finite() {
+            Some(String::from("gain_db must be a finite number"))
+        } else if !(self.q > 0.0 && self.q.is_finite()) {
+            Some(String::from("q must be a positive number"))
+        } else {
+            None
+        }
+    }
+}
+
+/// Second order iir filter in transposed direct form II, with coefficients from the audio eq
+/// cookbook
+#[derive(Debug, Clone)]
+struct Biquad {
+    // feedforward and feedback coefficients, normalized so a0 is 1
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+            state: [0.0; 2],
+        }
+    }
+
+    fn highpass(freq_hz: f64, q: f64, rate: f64) -> Self {
+        let (cos, alpha) = Self::prewarp(freq_hz, q, rate);
+        Self::new(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn peaking(band: &EqBand, rate: f64) -> Self {
+        let (cos, alpha) = Self::prewarp(band.freq_hz, band.q, rate);
+        let amplitude = 10f64.powf(band.gain_db / 40.0);
+        Self::new(
+            [1.0 + alpha * amplitude, -2.0 * cos, 1.0 - alpha * amplitude],
+            [1.0 + alpha / amplitude, -2.0 * cos, 1.0 - alpha / amplitude],
+        )
+    }
+
+    /// First order pre-emphasis, y[n] = x[n] - coefficient * x[n - 1]
+    fn pre_emphasis(coefficient: f64) -> Self {
+        Self::new([1.0, -coefficient, 0.0], [1.0, 0.0, 0.0])
+    }
+
+    /// The cosine of the center frequency and the bandwidth term shared by the cookbook filters
+    fn prewarp(freq_hz: f64, q: f64, rate: f64) -> (f64, f64) {
+        let omega = 2.0 * PI * freq_hz / rate;
+        (omega.cos(), omega.sin() / (2.0 * q))
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The time domain filters applied to the audio before the fft, in order a high-pass to take
+/// out dc and rumble, pre-emphasis to tilt the spectrum towards the treble, and the eq bands.
+/// Boomy rooms can otherwise saturate the lowest mel bands.
+#[derive(Debug, Clone, Default)]
+pub struct InputFilter {
+    filters: Vec<Biquad>,
+}
+
+impl InputFilter {
+    pub fn new(config: &Config) -> Self {
+        let rate = config.mic_rate as f64;
+        let highpass = (config.highpass_hz > 0.0)
+            .then(|| Biquad::highpass(config.highpass_hz, HIGHPASS_Q, rate));
+        let pre_emphasis =
+            (config.pre_emphasis > 0.0).then(|| Biquad::pre_emphasis(config.pre_emphasis));
+        let eq = config.eq.iter().map(|band| Biquad::peaking(band, rate));
+        Self {
+            filters: highpass.into_iter().chain(pre_emphasis).chain(eq).collect(),
+        }
+    }
+
+    pub fn process(&mut self, sample: f64) -> f64 {
+        self.filters
+            .iter_mut()
+            .fold(sample, |sample, filter| filter.process(sample))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    /// Peak amplitude of a unit sine at `freq_hz` once it has been through the filter for a
+    /// second
+    fn response(filter: &mut InputFilter, freq_hz: f64, rate: f64) -> f64 {
+        let output: Vec<f64> = (0..rate as usize)
+            .map(|i| filter.process((2.0 * PI * freq_hz * i as f64 / rate).sin()))
+            .collect();
+        output[output.len() / 2..]
+            .iter()
+            .fold(0.0, |a: f64, b| a.max(b.abs()))
+    }
+
+    #[test]
+    fn test_highpass_removes_dc_and_passes_treble() {
+        let config = Config {
+            highpass_hz: 40.0,
+            ..Default::default()
+        };
+        let rate = config.mic_rate as f64;
+        let mut filter = InputFilter::new(&config);
+        let dc = (0..config.mic_rate).map(|_| filter.process(0.5)).last();
+        assert_abs_diff_eq!(dc.unwrap(), 0.0, epsilon = 1e-6);
+        assert_abs_diff_eq!(response(&mut filter, 2000.0, rate), 1.0, epsilon = 1e-2);
+        // -3 dB at the cutoff
+        let mut filter = InputFilter::new(&config);
+        assert_abs_diff_eq!(
+            response(&mut filter, 40.0, rate),
+            HIGHPASS_Q,
+            epsilon = 1e-2
+        );
+    }
+
+    #[test]
+    fn test_eq_band_gain_at_center() {
+        let config = Config {
+            eq: vec![EqBand {
+                freq_hz: 100.0,
+                gain_db: -6.0,
+                q: 2.0,
+            }],
+            ..Default::default()
+        };
+        let rate = config.mic_rate as f64;
+        let mut filter = InputFilter::new(&config);
+        let gain_db = 20.0 * response(&mut filter, 100.0, rate).log10();
+        assert_abs_diff_eq!(gain_db, -6.0, epsilon = 0.1);
+        // far from the band nothing changes
+        assert_abs_diff_eq!(response(&mut filter, 5000.0, rate), 1.0, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn test_pre_emphasis_tilts_towards_treble() {
+        let config = Config {
+            pre_emphasis: 0.97,
+            ..Default::default()
+        };
+        let rate = config.mic_rate as f64;
+        let low = response(&mut InputFilter::new(&config), 100.0, rate);
+        let high = response(&mut InputFilter::new(&config), 10000.0, rate);
+        assert!(high > 10.0 * low, "low {low}, high {high}");
+    }
+}
@@ -0,0 +1,74 @@
+/// The gate opens once the level rises this many times above the threshold, and closes when it
+/// falls below the threshold, so levels hovering around it don't make the output flicker
+const GATE_HYSTERESIS: f64 = 2.0;
+
+/// Noise gate on the input level. While closed, the output fades to black instead of the adaptive
+/// gain amplifying room noise.
+pub struct NoiseGate {
+    threshold: f64,
+    open: bool,
+    level: f64,
+    // how much the level falls each frame while the gate is closed
+    release_step: f64,
+}
+
+impl NoiseGate {
+    pub fn new(threshold: f64, release_ms: u32, fps: u32) -> Self {
+        let release_frames = release_ms as f64 / 1000.0 * fps as f64;
+        Self {
+            threshold,
+            open: false,
+            level: 0.0,
+            release_step: if release_frames > 1.0 {
+                1.0 / release_frames
+            } else {
+                1.0
+            },
+        }
+    }
+
+    /// Update the gate with the rms level of the latest audio, returning the output level from 0
+    /// when fully closed to 1 when open
+    pub fn update(&mut self, rms: f64) -> f64 {
+        if rms >= self.threshold * GATE_HYSTERESIS {
+            self.open = true;
+        } else if rms < self.threshold {
+            self.open = false;
+        }
+        self.level = if self.open {
+            1.0
+        } else {
+            (self.level - self.release_step).max(0.0)
+        };
+        self.level
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gate_hysteresis_and_release() {
+        // a 100 ms release at 50 fps fades out over 5 frames
+        let mut gate = NoiseGate::new(1.0, 100, 50);
+        assert_eq!(gate.update(1.5), 0.0);
+        assert!(!gate.is_open());
+        assert_eq!(gate.update(2.0), 1.0);
+        // between the thresholds the gate stays open
+        assert_eq!(gate.update(1.5), 1.0);
+        assert!(gate.is_open());
+
+        let levels: Vec<f64> = (0..6).map(|_| gate.update(0.5)).collect();
+        assert!(!gate.is_open());
+        for (level, expected) in levels.iter().zip([0.8, 0.6, 0.4, 0.2, 0.0, 0.0]) {
+            assert!((level - expected).abs() < 1e-9, "{:?}", levels);
+        }
+        // and between the thresholds it stays closed
+        assert_eq!(gate.update(1.5), 0.0);
+    }
+}
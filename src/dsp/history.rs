@@ -0,0 +1,138 @@
+use super::InputFilter;
+
+/// Fixed length history of the newest audio samples, kept in a ring so that adding samples
+/// doesn't move or allocate anything. Samples go through the input filter on the way in.
+pub struct History {
+    samples: Vec<f64>,
+    // index of the oldest sample, where the next one is written
+    next: usize,
+    filter: InputFilter,
+}
+
+impl History {
+    pub fn new(len: usize) -> Self {
+        Self {
+            samples: vec![0.0; len],
+            next: 0,
+            filter: InputFilter::default(),
+        }
+    }
+
+    /// Filter samples added from now on with `filter`
+    pub fn set_filter(&mut self, filter: InputFilter) {
+        self.filter = filter;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Add new samples, dropping as many of the oldest
+    #[cfg(test)]
+    pub fn push(&mut self, new_samples: &[f32]) {
+        self.extend(new_samples.iter().copied());
+    }
+
+    /// Add one channel of interleaved samples with `channels` channels
+    pub fn push_channel(&mut self, interleaved: &[f32], channels: usize, channel: usize) {
+        self.extend(
+            interleaved
+                .chunks_exact(channels)
+                .map(|frame| frame[channel]),
+        );
+    }
+
+    /// Add the average of every channel of interleaved samples with `channels` channels
+    pub fn push_downmix(&mut self, interleaved: &[f32], channels: usize) {
+        self.extend(
+            interleaved
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+    }
+
+    fn extend(&mut self, new_samples: impl Iterator<Item = f32>) {
+        let len = self.samples.len();
+        // samples older than a full history are overwritten, but still pass through the filter
+        // to keep its state continuous
+        for sample in new_samples {
+            self.samples[self.next] = self.filter.process(sample as f64);
+            self.next = (self.next + 1) % len;
+        }
+    }
+
+    /// The samples from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        self.samples[self.next..]
+            .iter()
+            .chain(&self.samples[..self.next])
+            .copied()
+    }
+
+    /// Change the length, keeping the newest samples and padding with zeros at the front if the
+    /// history grew
+    pub fn resize(&mut self, len: usize) {
+        let kept = self.len().min(len);
+        let mut samples = vec![0.0; len];
+        for (new, old) in samples[len - kept..]
+            .iter_mut()
+            .zip(self.iter().skip(self.len() - kept))
+        {
+            *new = old;
+        }
+        self.samples = samples;
+        self.next = 0;
+    }
+
+    pub fn rms(&self) -> f64 {
+        (self.samples.iter().map(|x| x.powi(2)).sum::<f64>() / self.len() as f64).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history_keeps_newest_samples_in_order() {
+        let mut history = History::new(4);
+        history.push(&[1.0, 2.0, 3.0]);
+        history.push(&[4.0, 5.0]);
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![2.0, 3.0, 4.0, 5.0]);
+
+        // more samples than fit only keep the newest
+        history.push(&[6.0, 7.0, 8.0, 9.0, 10.0, 11.0]);
+        assert_eq!(
+            history.iter().collect::<Vec<_>>(),
+            vec![8.0, 9.0, 10.0, 11.0]
+        );
+
+        history.resize(6);
+        assert_eq!(
+            history.iter().collect::<Vec<_>>(),
+            vec![0.0, 0.0, 8.0, 9.0, 10.0, 11.0]
+        );
+        history.resize(2);
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![10.0, 11.0]);
+    }
+
+    #[test]
+    fn test_interleaved_channels() {
+        let stereo = [1.0, -1.0, 2.0, 0.0, 3.0, 1.0];
+        let mut left = History::new(3);
+        left.push_channel(&stereo, 2, 0);
+        assert_eq!(left.iter().collect::<Vec<_>>(), vec![1.0, 2.0, 3.0]);
+
+        let mut right = History::new(2);
+        right.push_channel(&stereo, 2, 1);
+        assert_eq!(right.iter().collect::<Vec<_>>(), vec![0.0, 1.0]);
+
+        let mut mid = History::new(3);
+        mid.push_downmix(&stereo, 2);
+        assert_eq!(mid.iter().collect::<Vec<_>>(), vec![0.0, 1.0, 2.0]);
+    }
+}
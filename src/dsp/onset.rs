@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use ndarray::Array1;
+
+/// How far back the adaptive thresholds look
+const THRESHOLD_WINDOW: Duration = Duration::from_secs(1);
+/// How many standard deviations above the running mean the flux has to rise to count as an onset
+const THRESHOLD_STDDEVS: f64 = 2.0;
+/// Flux always has to exceed this, so noise in silent passages doesn't trigger onsets
+const MIN_FLUX: f64 = 0.05;
+/// Shortest gap between two beats, 100 ms is faster than any drummer
+const MIN_BEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Onsets found in a single frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Onset {
+    /// Whether a beat starts in this frame
+    pub beat: bool,
+    /// How far the flux rose above the threshold, from 0 to 1. Zero unless `beat` is set.
+    pub confidence: f64,
+    /// Spectral flux summed over every band, the onset envelope
+    pub flux: f64,
+    /// Onset strength of each mel band from 0 to 1, zero for bands without an onset
+    pub bands: Array1<f64>,
+}
+
+impl Onset {
+    pub fn none(n_bands: usize) -> Self {
+        Self {
+            beat: false,
+            confidence: 0.0,
+            flux: 0.0,
+            bands: Array1::zeros(n_bands),
+        }
+    }
+}
+
+/// Running mean and variance with an exponential window
+struct RunningStats<T> {
+    mean: T,
+    var: T,
+}
+
+/// Spectral flux onset detector on the mel spectrum, with thresholds that adapt to the recent
+/// flux so that loud and quiet passages both produce onsets
+pub struct OnsetDetector {
+    prev_spectrum: Option<Array1<f64>>,
+    flux: RunningStats<f64>,
+    band_flux: RunningStats<Array1<f64>>,
+    window_frames: usize,
+    frames: usize,
+    frames_since_beat: usize,
+    min_beat_frames: usize,
+}
+
+impl OnsetDetector {
+    pub fn new(n_bands: usize, fps: u32) -> Self {
+        let window_frames = (THRESHOLD_WINDOW.as_secs_f64() * fps as f64).max(1.0) as usize;
+        Self {
+            prev_spectrum: None,
+            flux: RunningStats {
+                mean: 0.0,
+                var: 0.0,
+            },
+            band_flux: RunningStats {
+                mean: Array1::zeros(n_bands),
+                var: Array1::zeros(n_bands),
+            },
+            window_frames,
+            frames: 0,
+            frames_since_beat: 0,
+            min_beat_frames: (MIN_BEAT_INTERVAL.as_secs_f64() * fps as f64).ceil() as usize,
+        }
+    }
+
+    /// Find the onsets in a new frame of the mel spectrum, as returned by `Dsp::get_mel_repr`
+    pub fn update(&mut self, mel: &Array1<f64>) -> Onset {
+        // log compression evens out the loud low bands and the quiet high ones
+        let spectrum = mel.mapv(f64::ln_1p);
+        let Some(prev_spectrum) = self.prev_spectrum.replace(spectrum.clone()) else {
+            return Onset::none(spectrum.len());
+        };
+        // half-wave rectify, only rising energy marks an onset
+        let band_flux = (&spectrum - &prev_spectrum).mapv(|x| x.max(0.0));
+        let flux = band_flux.sum();
+
+        let threshold = self.flux.mean + THRESHOLD_STDDEVS * self.flux.var.sqrt() + MIN_FLUX;
+        let band_threshold = self
+            .band_flux
+            .var
+            .mapv(|var| THRESHOLD_STDDEVS * var.sqrt() + MIN_FLUX / spectrum.len() as f64)
+            + &self.band_flux.mean;
+        self.update_stats(flux, &band_flux);
+
+        self.frames_since_beat += 1;
+        // the thresholds mean nothing until a full window has been seen
+        if self.frames <= self.window_frames {
+            return Onset::none(spectrum.len());
+        }
+
+        let beat = flux > threshold && self.frames_since_beat >= self.min_beat_frames;
+        if beat {
+            self.frames_since_beat = 0;
+        }
+        let mut bands = band_flux;
+        bands.zip_mut_with(&band_threshold, |b, t| *b = onset_strength(*b, *t));
+        Onset {
+            beat,
+            confidence: if beat {
+                onset_strength(flux, threshold)
+            } else {
+                0.0
+            },
+            flux,
+            bands,
+        }
+    }
+
+    fn update_stats(&mut self, flux: f64, band_flux: &Array1<f64>) {
+        // a plain average until the window fills, so the statistics start out accurate
+        self.frames += 1;
+        let alpha = 1.0 / self.frames.min(self.window_frames) as f64;
+        let diff = flux - self.flux.mean;
+        self.flux.mean += alpha * diff;
+        self.flux.var = (1.0 - alpha) * (self.flux.var + alpha * diff.powi(2));
+
+        let diff = band_flux - &self.band_flux.mean;
+        self.band_flux.mean.scaled_add(alpha, &diff);
+        self.band_flux.var.zip_mut_with(&diff, |var, d| {
+            *var = (1.0 - alpha) * (*var + alpha * d.powi(2))
+        });
+    }
+}
+
+/// Strength of an onset from 0 to 1, reaching 1 at twice the threshold
+fn onset_strength(flux: f64, threshold: f64) -> f64 {
+    if flux > threshold {
+        ((flux - threshold) / threshold).min(1.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use ndarray_rand::{
+        rand::{rngs::StdRng, Rng, SeedableRng},
+        rand_distr::Uniform,
+    };
+
+    use crate::{
+        config::Config,
+        dsp::{history::History, Dsp},
+    };
+
+    /// Run a signal through the dsp a frame at a time, returning the frames in which beats fell
+    fn beat_frames(signal: &[f64], config: &Config) -> Vec<usize> {
+        let mut dsp = Dsp::new(config.clone());
+        let mut history = History::new(config.n_fft_bins as usize);
+        let signal: Vec<f32> = signal.iter().map(|x| *x as f32).collect();
+        signal
+            .chunks_exact((config.mic_rate / config.fps) as usize)
+            .enumerate()
+            .filter_map(|(frame, chunk)| {
+                history.push(chunk);
+                dsp.analyze(&history).onset.beat.then_some(frame)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_click_track() {
+        let config = Config::default();
+        let rate = config.mic_rate as f64;
+        let mut rng = StdRng::seed_from_u64(31);
+        let noise = Uniform::new(-1e-3, 1e-3);
+
+        // a quiet hum under clicks at 120 bpm, leaving the detector a second to settle
+        let click_times = [1.5, 2.0, 2.5, 3.0, 3.5];
+        let mut signal: Vec<f64> = (0..(4.0 * rate) as usize)
+            .map(|i| 0.05 * (2.0 * PI * 220.0 * i as f64 / rate).sin() + rng.sample(noise))
+            .collect();
+        for time in click_times {
+            let start = (time * rate) as usize;
+            for i in 0..(0.01 * rate) as usize {
+                let t = i as f64 / rate;
+                signal[start + i] += (-t / 0.002).exp() * (2.0 * PI * 3000.0 * t).sin();
+            }
+        }
+
+        let hop = (config.mic_rate / config.fps) as f64;
+        let expected: Vec<usize> = click_times
+            .iter()
+            .map(|time| (time * rate / hop) as usize)
+            .collect();
+        let beats = beat_frames(&signal, &config);
+        assert_eq!(beats.len(), expected.len(), "beats at {:?}", beats);
+        for (beat, click) in beats.iter().zip(expected) {
+            assert!(
+                beat.abs_diff(click) <= 1,
+                "beat at {beat}, click at {click}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_steady_tone_has_no_onsets() {
+        let config = Config::default();
+        let rate = config.mic_rate as f64;
+        let signal: Vec<f64> = (0..(3.0 * rate) as usize)
+            .map(|i| 0.5 * (2.0 * PI * 440.0 * i as f64 / rate).sin())
+            .collect();
+        assert!(beat_frames(&signal, &config).is_empty());
+    }
+}
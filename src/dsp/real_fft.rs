@@ -0,0 +1,96 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use ndarray::Array1;
+use rustfft::{num_complex::Complex64, Fft, FftPlanner};
+
+/// Magnitude spectrum of real input through a complex fft of half the size, with every buffer
+/// allocated up front so that transforming a frame doesn't allocate
+pub struct RealFft {
+    fft: Arc<dyn Fft<f64>>,
+    // e^(-2 pi i k / size), used to separate the spectra of the even and odd samples
+    twiddles: Vec<Complex64>,
+    buffer: Vec<Complex64>,
+    scratch: Vec<Complex64>,
+    magnitudes: Array1<f64>,
+}
+
+impl RealFft {
+    /// Plan a transform of `size` real samples, which must be even
+    pub fn new(size: usize) -> Self {
+        assert!(
+            size >= 2 && size.is_multiple_of(2),
+            "real fft size must be even"
+        );
+        let half = size / 2;
+        let fft = FftPlanner::new().plan_fft_forward(half);
+        Self {
+            twiddles: (0..half)
+                .map(|k| Complex64::from_polar(1.0, -2.0 * PI * k as f64 / size as f64))
+                .collect(),
+            buffer: vec![Complex64::default(); half],
+            scratch: vec![Complex64::default(); fft.get_inplace_scratch_len()],
+            magnitudes: Array1::zeros(half),
+            fft,
+        }
+    }
+
+    /// Transform `samples`, which must yield exactly `size` values
+    pub fn transform(&mut self, samples: impl IntoIterator<Item = f64>) {
+        // pack pairs of real samples into one complex sample each
+        let mut n_samples = 0;
+        for (i, sample) in samples.into_iter().enumerate() {
+            let packed = &mut self.buffer[i / 2];
+            if i % 2 == 0 {
+                packed.re = sample;
+            } else {
+                packed.im = sample;
+            }
+            n_samples = i + 1;
+        }
+        assert_eq!(n_samples, 2 * self.buffer.len(), "wrong number of samples");
+
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        // the spectra of the even and odd samples are the conjugate symmetric and antisymmetric
+        // parts of the packed spectrum, which recombine into the spectrum of the whole input
+        let half = self.buffer.len();
+        for k in 0..half {
+            let packed = self.buffer[k];
+            let mirrored = self.buffer[(half - k) % half].conj();
+            let even = (packed + mirrored) * 0.5;
+            let odd = (packed - mirrored) * Complex64::new(0.0, -0.5);
+            self.magnitudes[k] = (even + self.twiddles[k] * odd).norm();
+        }
+    }
+
+    /// The magnitudes of the first `size / 2` bins of the last transformed spectrum
+    pub fn magnitudes(&self) -> &Array1<f64> {
+        &self.magnitudes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_matches_complex_fft() {
+        let size = 32;
+        let samples: Vec<f64> = (0..size)
+            .map(|i| (i as f64 * 0.7).sin() + 0.3 * (i as f64 * 2.1).cos() + 0.1)
+            .collect();
+
+        let mut complex: Vec<Complex64> = samples.iter().map(|x| Complex64::new(*x, 0.0)).collect();
+        FftPlanner::new()
+            .plan_fft_forward(size)
+            .process(&mut complex);
+        let expected: Array1<f64> = complex[..size / 2].iter().map(|x| x.norm()).collect();
+
+        let mut fft = RealFft::new(size);
+        fft.transform(samples.iter().copied());
+        assert_abs_diff_eq!(*fft.magnitudes(), expected, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,127 @@
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+
+use super::{hertz_to_mel, mel_to_hertz};
+
+/// Frequency scale that the filter bank's bands are spaced evenly on
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrequencyScale {
+    Mel,
+    /// Traunmüller's Bark scale of critical bands
+    Bark,
+    Linear,
+    /// Equal width in octaves, e.g. 30 bands over the 10 octaves from 20 Hz to 20 kHz are
+    /// third-octave bands
+    Log,
+    /// Like `Log`, with every band edge moved to the nearest note of the equal tempered scale
+    Notes,
+}
+
+/// Frequency of the note `semitones` away from A4
+fn note_to_hertz(semitones: f64) -> f64 {
+    440.0 * 2f64.powf(semitones / 12.0)
+}
+
+pub(super) fn hertz_to_note(hertz: f64) -> f64 {
+    12.0 * (hertz / 440.0).log2()
+}
+
+impl FrequencyScale {
+    fn scale_of(self, hertz: f64) -> f64 {
+        match self {
+            FrequencyScale::Mel => hertz_to_mel(hertz),
+            FrequencyScale::Bark => 26.81 * hertz / (1960.0 + hertz) - 0.53,
+            FrequencyScale::Linear => hertz,
+            FrequencyScale::Log | FrequencyScale::Notes => hertz.log2(),
+        }
+    }
+
+    fn hertz_of(self, value: f64) -> f64 {
+        match self {
+            FrequencyScale::Mel => mel_to_hertz(value),
+            FrequencyScale::Bark => 1960.0 * (value + 0.53) / (26.28 - value),
+            FrequencyScale::Linear => value,
+            FrequencyScale::Log | FrequencyScale::Notes => 2f64.powf(value),
+        }
+    }
+
+    /// The `n_bands + 2` edges of `n_bands` overlapping triangular bands between `min_freq_hz`
+    /// and `max_freq_hz`, where band `i` rises from edge `i` to peak at edge `i + 1`
+    pub fn band_edges(self, min_freq_hz: f64, max_freq_hz: f64, n_bands: usize) -> Array1<f64> {
+        let (min, max) = (self.scale_of(min_freq_hz), self.scale_of(max_freq_hz));
+        let edges = Array1::linspace(min, max, n_bands + 2).mapv(|x| self.hertz_of(x));
+        match self {
+            FrequencyScale::Notes => edges.mapv(|hz| note_to_hertz(hertz_to_note(hz).round())),
+            _ => edges,
+        }
+    }
+
+    /// Check that `n_bands` distinct bands fit between `min_freq_hz` and `max_freq_hz` on this
+    /// scale, describing the problem if not
+    pub fn check_range(self, min_freq_hz: f64, max_freq_hz: f64, n_bands: usize) -> Option<String> {
+        match self {
+            FrequencyScale::Log | FrequencyScale::Notes if min_freq_hz <= 0.0 => {
+                Some(format!("{:?} needs a min_freq_hz above 0", self))
+            }
+            FrequencyScale::Notes => {
+                let edges = self.band_edges(min_freq_hz, max_freq_hz, n_bands);
+                let distinct = edges.windows(2).into_iter().all(|w| w[0] < w[1]);
+                (!distinct).then(|| {
+                    format!(
+                        "Notes needs a semitone for each of its {} band edges between min_freq_hz \
+                         and max_freq_hz",
+                        n_bands + 2
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_band_edges_span_the_range() {
+        for scale in [
+            FrequencyScale::Mel,
+            FrequencyScale::Bark,
+            FrequencyScale::Linear,
+            FrequencyScale::Log,
+        ] {
+            let edges = scale.band_edges(50.0, 12000.0, 24);
+            assert_eq!(edges.len(), 26);
+            assert_abs_diff_eq!(edges[0], 50.0, epsilon = 1e-6);
+            assert_abs_diff_eq!(edges[25], 12000.0, epsilon = 1e-6);
+            assert!(edges.windows(2).into_iter().all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[test]
+    fn test_log_and_note_edges() {
+        // four octaves in eleven steps of a third of an octave, plus the outer edges
+        let edges = FrequencyScale::Log.band_edges(110.0, 1760.0, 10);
+        for w in edges.windows(2) {
+            assert_abs_diff_eq!(w[1] / w[0], 2f64.powf(4.0 / 11.0), epsilon = 1e-12);
+        }
+
+        // every edge of the notes scale is an equal tempered note
+        let edges = FrequencyScale::Notes.band_edges(100.0, 2000.0, 20);
+        for edge in edges {
+            let note = hertz_to_note(edge);
+            assert_abs_diff_eq!(note, note.round(), epsilon = 1e-9);
+        }
+        assert!(FrequencyScale::Notes
+            .check_range(100.0, 2000.0, 20)
+            .is_none());
+        // a single octave only has 12 semitones to go around
+        assert!(FrequencyScale::Notes
+            .check_range(440.0, 880.0, 12)
+            .is_some());
+    }
+}
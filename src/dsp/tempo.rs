@@ -0,0 +1,202 @@
+use std::{collections::VecDeque, time::Duration};
+
+use super::Onset;
+
+/// How much of the onset envelope the tempo is estimated from
+const TEMPO_WINDOW: Duration = Duration::from_secs(8);
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+/// Tempo that the estimate leans towards when several are plausible, and the spread of that
+/// preference in octaves
+const PREFERRED_BPM: f64 = 120.0;
+const PREFERRED_BPM_OCTAVES: f64 = 1.0;
+/// Normalized autocorrelation needed to trust a new estimate. Below it, e.g. in quiet passages,
+/// the last tempo is kept.
+const MIN_CONFIDENCE: f64 = 0.1;
+/// How quickly the tempo follows small drifts in the estimate
+const BPM_SMOOTHING: f64 = 0.2;
+/// How far each beat pulls the phase towards it
+const PHASE_CORRECTION: f64 = 0.2;
+
+/// The tempo and where the latest frame falls within the current beat
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tempo {
+    /// Estimated beats per minute, if a tempo has been found yet
+    pub bpm: Option<f64>,
+    /// Position within the current beat, from 0 at the beat to 1 just before the next one
+    pub phase: f64,
+}
+
+impl Tempo {
+    pub fn none() -> Self {
+        Self {
+            bpm: None,
+            phase: 0.0,
+        }
+    }
+}
+
+/// Estimates the tempo from the autocorrelation of the onset envelope, and keeps a beat phase
+/// locked to the detected beats that carries on at the estimated tempo when they stop
+pub struct TempoTracker {
+    envelope: VecDeque<f64>,
+    window_frames: usize,
+    fps: f64,
+    tempo: Tempo,
+}
+
+impl TempoTracker {
+    pub fn new(fps: u32) -> Self {
+        let window_frames = (TEMPO_WINDOW.as_secs_f64() * fps as f64) as usize;
+        Self {
+            envelope: VecDeque::with_capacity(window_frames),
+            window_frames,
+            fps: fps as f64,
+            tempo: Tempo::none(),
+        }
+    }
+
+    /// Update the tempo with the onsets of a new frame
+    pub fn update(&mut self, onset: &Onset) -> Tempo {
+        if self.envelope.len() == self.window_frames {
+            self.envelope.pop_front();
+        }
+        self.envelope.push_back(onset.flux);
+
+        if let Some(bpm) = self.estimate_bpm() {
+            self.tempo.bpm = Some(match self.tempo.bpm {
+                // follow small drifts smoothly, but jump to a tempo that is clearly new
+                Some(current) if (bpm - current).abs() < 0.1 * current => {
+                    current + BPM_SMOOTHING * (bpm - current)
+                }
+                _ => bpm,
+            });
+        }
+
+        match self.tempo.bpm {
+            Some(bpm) => {
+                self.tempo.phase = (self.tempo.phase + bpm / 60.0 / self.fps).fract();
+                if onset.beat {
+                    // the distance from the beat, from -0.5 to 0.5
+                    let error = self.tempo.phase - self.tempo.phase.round();
+                    self.tempo.phase =
+                        (self.tempo.phase - PHASE_CORRECTION * error).rem_euclid(1.0);
+                }
+            }
+            None if onset.beat => self.tempo.phase = 0.0,
+            None => {}
+        }
+        self.tempo.clone()
+    }
+
+    /// The tempo at the strongest peak of the envelope's autocorrelation, or `None` if there
+    /// isn't enough envelope yet or it has no clear periodicity
+    fn estimate_bpm(&self) -> Option<f64> {
+        let min_lag = (60.0 * self.fps / MAX_BPM).floor().max(1.0) as usize;
+        let max_lag = (60.0 * self.fps / MIN_BPM).ceil() as usize;
+        // a few periods of the slowest tempo are needed to see its periodicity
+        if self.envelope.len() < 2 * max_lag + 1 {
+            return None;
+        }
+
+        let mean = self.envelope.iter().sum::<f64>() / self.envelope.len() as f64;
+        let envelope: Vec<f64> = self.envelope.iter().map(|x| x - mean).collect();
+        let autocorrelation = |lag: usize| -> f64 {
+            envelope
+                .iter()
+                .zip(&envelope[lag..])
+                .map(|(a, b)| a * b)
+                .sum()
+        };
+        let energy = autocorrelation(0);
+        if energy <= 0.0 {
+            return None;
+        }
+
+        let correlations: Vec<f64> = (min_lag - 1..=max_lag + 1).map(autocorrelation).collect();
+        let prior = |lag: f64| {
+            let octaves = (60.0 * self.fps / lag / PREFERRED_BPM).log2() / PREFERRED_BPM_OCTAVES;
+            (-0.5 * octaves.powi(2)).exp()
+        };
+        let (best, _) = (1..correlations.len() - 1)
+            .map(|i| (i, correlations[i] * prior((min_lag + i - 1) as f64)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        if correlations[best] / energy < MIN_CONFIDENCE {
+            return None;
+        }
+
+        // fit a parabola through the peak for a lag between frames
+        let (left, peak, right) = (
+            correlations[best - 1],
+            correlations[best],
+            correlations[best + 1],
+        );
+        let curvature = left - 2.0 * peak + right;
+        let offset = if curvature < 0.0 {
+            (0.5 * (left - right) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let lag = (min_lag + best - 1) as f64 + offset;
+        Some(60.0 * self.fps / lag)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::Array1;
+
+    use super::*;
+
+    /// Onsets with beats at the given frames and nothing in between
+    fn beat_onsets(n_frames: usize, beats: &[usize]) -> Vec<Onset> {
+        (0..n_frames)
+            .map(|frame| {
+                let beat = beats.contains(&frame);
+                Onset {
+                    beat,
+                    confidence: if beat { 1.0 } else { 0.0 },
+                    flux: if beat { 1.0 } else { 0.0 },
+                    bands: Array1::zeros(1),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tempo_follows_beats() {
+        let fps = 60;
+        let mut tracker = TempoTracker::new(fps);
+        // 128 bpm doesn't fall on a whole number of frames
+        let period = 60.0 * fps as f64 / 128.0;
+        let beats: Vec<usize> = (0..40)
+            .map(|i| (i as f64 * period).round() as usize)
+            .collect();
+        let tempo = beat_onsets(600, &beats)
+            .iter()
+            .map(|onset| tracker.update(onset))
+            .last()
+            .unwrap();
+        let bpm = tempo.bpm.unwrap();
+        assert!((bpm - 128.0).abs() < 1.0, "estimated {bpm} bpm");
+    }
+
+    #[test]
+    fn test_phase_carries_through_silence() {
+        let fps = 60;
+        let mut tracker = TempoTracker::new(fps);
+        // 120 bpm for 10 seconds, then silence
+        let beats: Vec<usize> = (0..20).map(|i| i * 30).collect();
+        let onsets = beat_onsets(900, &beats);
+        let tempos: Vec<Tempo> = onsets.iter().map(|onset| tracker.update(onset)).collect();
+
+        for frame in [600, 630, 660, 690, 720, 899] {
+            let tempo = &tempos[frame];
+            assert!((tempo.bpm.unwrap() - 120.0).abs() < 1.0);
+            // where the beats would have fallen, the phase is still at the start of a beat
+            let expected = (frame % 30) as f64 / 30.0;
+            let error = (tempo.phase - expected + 0.5).rem_euclid(1.0) - 0.5;
+            assert!(error.abs() < 0.05, "phase {} at frame {frame}", tempo.phase);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::f64::consts::PI;
+
+use ndarray::Array1;
+use serde::{Deserialize, Serialize};
+
+/// Window applied to the audio history before the fft, trading a little frequency resolution for
+/// much less leakage between neighbouring bins
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Window {
+    None,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+}
+
+impl Window {
+    /// The symmetric window of `size` samples, matching numpy's `hanning` and `hamming` and
+    /// scipy's `blackmanharris(size, sym=True)`
+    pub fn coefficients(self, size: usize) -> Array1<f64> {
+        let cosine_terms: &[f64] = match self {
+            Window::None => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+        };
+        if size == 1 {
+            return Array1::ones(1);
+        }
+        Array1::from_shape_fn(size, |i| {
+            let x = 2.0 * PI * i as f64 / (size - 1) as f64;
+            cosine_terms
+                .iter()
+                .enumerate()
+                .map(|(k, a)| if k % 2 == 0 { 1.0 } else { -1.0 } * a * (k as f64 * x).cos())
+                .sum()
+        })
+    }
+}
@@ -13,8 +13,6 @@ pub struct ESP8266Conn {
     address: SocketAddr,
 }
 
-static MAX_PIXELS_PER_PACKET: usize = 126;
-
 impl ESP8266Conn {
     /// Create a new ESP8266 connection with a specified ip and gamma correction. The socket throws
     /// an io error if it cannot bind
@@ -32,11 +30,11 @@ impl ESP8266Conn {
     }
 
     /// Sends UDP packets to ESP8266 to update LED strip values
-
+    ///
     /// The ESP8266 will receive and decode the packets to determine what values
     /// to display on the LED strip. The communication protocol supports LED strips
     /// with a maximum of 256 LEDs.
-
+    ///
     /// The packet encoding scheme is:
     ///     |i|r|g|b|
     /// where
@@ -126,7 +124,7 @@ mod test {
                         .collect::<Vec<u8>>()
                         .chunks(1)
                 )
-                .flat_map(|(a, b)| b.into_iter().chain(a))
+                .flat_map(|(a, b)| b.iter().chain(a))
                 .copied()
                 .collect::<Vec<u8>>(),
             send_buffer
@@ -160,7 +158,7 @@ mod test {
         let recv = UdpSocket::bind("127.0.0.1:7777").unwrap();
         let mut buf: Vec<u8> = vec![0; 2048];
         let send_len = send
-            .update(&mut pixels.clone(), &mut pixels_prev.clone())
+            .update(&mut pixels.clone(), &pixels_prev.clone())
             .unwrap();
         let recv_len = recv.recv(&mut buf).unwrap();
         assert_eq!(send_len, num_different * 4);
@@ -178,7 +176,7 @@ mod test {
                         .collect::<Vec<u8>>()
                         .chunks(1)
                 )
-                .flat_map(|(a, b)| b.into_iter().chain(a))
+                .flat_map(|(a, b)| b.iter().chain(a))
                 .copied()
                 .collect::<Vec<u8>>()
         );
@@ -0,0 +1,25 @@
+//! Audio analysis and led strip output behind the audio reactive renderer.
+//!
+//! [`Dsp`] turns windows of audio into an [`Analysis`] of the sound, which the [`Effect`] of a
+//! [`Preset`] renders onto the strip. [`Output`] sends the rendered
+//! frames to an [`ESP8266Conn`] on a steady timer, and [`Renderer`] ties these together with
+//! audio capture from a [`Config`].
+
+pub mod args;
+pub mod audio;
+pub mod calibrate;
+pub mod config;
+pub mod display_mode;
+pub mod dsp;
+mod gamma_table;
+pub mod led;
+pub mod output;
+mod playback;
+pub mod renderer;
+mod ring_buffer;
+
+pub use config::Config;
+pub use dsp::{Analysis, Dsp, Effect, MelBank, Preset};
+pub use led::ESP8266Conn;
+pub use output::Output;
+pub use renderer::Renderer;
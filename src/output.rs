@@ -0,0 +1,374 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use ndarray::{s, Array2, ArrayView2};
+
+use crate::{led::ESP8266Conn, renderer::RenderStats};
+
+/// Change in the luminance or the saturated red of a segment of the strip, as a fraction of full
+/// brightness, that counts as a flash
+const FLASH_THRESHOLD: f64 = 0.1;
+/// Length of the segments flashes are looked for in, so that a flash of part of the strip is
+/// caught as well as one of the whole strip
+const FLASH_SEGMENT_PIXELS: usize = 8;
+
+enum OutputMessage {
+    Frame(Array2<u8>),
+    Connection(ESP8266Conn),
+    FrameDuration(Duration),
+    Delay(Duration),
+    MaxFlashHz(f64),
+}
+
+/// Sends the newest rendered frame to the led strip from its own thread on a steady timer, so
+/// that the strip updates at an even rate however unevenly the audio, and so the frames, arrive.
+/// Frames can be held back by a delay, to line the lights up with sound that reaches the
+/// listener late. Whatever the effect, the strip never flashes more often than `max_flash_hz`.
+/// The thread stops when this is dropped.
+pub struct Output {
+    tx: mpsc::Sender<OutputMessage>,
+}
+
+impl Output {
+    /// Start the output thread, counting the ticks without a new frame in `stats`
+    pub fn spawn(
+        conn: ESP8266Conn,
+        frame_duration: Duration,
+        delay: Duration,
+        max_flash_hz: f64,
+        stats: Arc<RenderStats>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            output_loop(rx, conn, frame_duration, delay, max_flash_hz, &stats);
+        });
+        Self { tx }
+    }
+
+    /// Queue a frame, to be sent at the first tick after the delay has passed
+    pub fn send_frame(&self, frame: Array2<u8>) {
+        self.send(OutputMessage::Frame(frame));
+    }
+
+    pub fn set_connection(&self, conn: ESP8266Conn) {
+        self.send(OutputMessage::Connection(conn));
+    }
+
+    pub fn set_frame_duration(&self, frame_duration: Duration) {
+        self.send(OutputMessage::FrameDuration(frame_duration));
+    }
+
+    /// Change the delay of frames rendered from now on
+    pub fn set_delay(&self, delay: Duration) {
+        self.send(OutputMessage::Delay(delay));
+    }
+
+    pub fn set_max_flash_hz(&self, max_flash_hz: f64) {
+        self.send(OutputMessage::MaxFlashHz(max_flash_hz));
+    }
+
+    fn send(&self, message: OutputMessage) {
+        self.tx
+            .send(message)
+            .expect("output thread should run until the output is dropped");
+    }
+}
+
+fn output_loop(
+    rx: mpsc::Receiver<OutputMessage>,
+    mut conn: ESP8266Conn,
+    frame_duration: Duration,
+    delay: Duration,
+    max_flash_hz: f64,
+    stats: &RenderStats,
+) {
+    let mut frame_duration = frame_duration;
+    let mut next_tick = Instant::now() + frame_duration;
+    let mut frames = DelayLine::new(delay);
+    let mut flashes = FlashLimiter::new(max_flash_hz);
+    let mut sent = Array2::<u8>::zeros((0, 3));
+    let mut started = false;
+    loop {
+        // take in messages until the tick is due
+        match rx.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+            Ok(OutputMessage::Frame(frame)) => frames.push(frame, Instant::now()),
+            Ok(OutputMessage::Connection(new_conn)) => {
+                conn = new_conn;
+                // the new connection's strip hasn't seen anything yet
+                sent = Array2::zeros((0, 3));
+            }
+            Ok(OutputMessage::FrameDuration(duration)) => frame_duration = duration,
+            Ok(OutputMessage::Delay(delay)) => frames.delay = delay,
+            Ok(OutputMessage::MaxFlashHz(max_flash_hz)) => flashes.set_max_flash_hz(max_flash_hz),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        if now < next_tick {
+            continue;
+        }
+
+        match frames.take_due(now) {
+            Some(mut frame) => {
+                flashes.limit(&mut frame, now);
+                if frame.dim() != sent.dim() {
+                    sent = Array2::zeros(frame.dim());
+                }
+                conn.update(&mut frame, &sent)
+                    .expect("error updating connection");
+                sent = frame;
+                started = true;
+            }
+            // the strip holds the last frame for another tick
+            None if started => stats.record_late_frame(),
+            None => {}
+        }
+        // schedule from the last tick rather than from now so that the rate doesn't drift, unless
+        // we fell a whole frame behind
+        next_tick += frame_duration;
+        if next_tick < now {
+            next_tick = now + frame_duration;
+        }
+    }
+}
+
+/// Holds frames back until `delay` after they were rendered
+struct DelayLine {
+    delay: Duration,
+    // frames along with when they're due
+    frames: VecDeque<(Instant, Array2<u8>)>,
+}
+
+impl DelayLine {
+    fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            frames: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, frame: Array2<u8>, now: Instant) {
+        self.frames.push_back((now + self.delay, frame));
+    }
+
+    /// The newest frame that is due by `now`, skipping any older ones that are also due
+    fn take_due(&mut self, now: Instant) -> Option<Array2<u8>> {
+        let mut due = None;
+        while self.frames.front().is_some_and(|(time, _)| *time <= now) {
+            due = self.frames.pop_front().map(|(_, frame)| frame);
+        }
+        due
+    }
+}
+
+/// Keeps the strip from flashing more often than is safe for photosensitive viewers by dimming
+/// any flash that comes too soon after the last one. A flash is a rise of at least
+/// [`FLASH_THRESHOLD`] in the luminance or the saturated red of any segment of the strip, after a
+/// fall of as much, so slow fades are left alone but a red to blue swap at the same brightness is
+/// not.
+struct FlashLimiter {
+    min_interval: Duration,
+    last_flash: Option<Instant>,
+    // the luminance and saturated red of each segment
+    segments: Vec<[Transitions; 2]>,
+}
+
+/// The rises and falls of one measure of a segment's light
+#[derive(Clone, Copy)]
+struct Transitions {
+    // the highest the level has been since the last flash, until it falls far enough from there
+    // to flash again, and the lowest it has been since then
+    peak: f64,
+    trough: Option<f64>,
+}
+
+impl Default for Transitions {
+    fn default() -> Self {
+        // the strip starts out dark
+        Self {
+            peak: 0.0,
+            trough: Some(0.0),
+        }
+    }
+}
+
+impl Transitions {
+    /// The highest `level` can rise to without flashing, if it is armed to flash at all
+    fn limit(&self) -> Option<f64> {
+        self.trough.map(|trough| trough + FLASH_THRESHOLD)
+    }
+
+    fn update(&mut self, level: f64) {
+        match self.trough {
+            Some(trough) if level >= trough + FLASH_THRESHOLD => {
+                self.peak = level;
+                self.trough = None;
+            }
+            Some(trough) => self.trough = Some(trough.min(level)),
+            None => {
+                self.peak = self.peak.max(level);
+                if level <= self.peak - FLASH_THRESHOLD {
+                    self.trough = Some(level);
+                }
+            }
+        }
+    }
+}
+
+impl FlashLimiter {
+    fn new(max_flash_hz: f64) -> Self {
+        Self {
+            min_interval: Duration::from_secs_f64(1.0 / max_flash_hz),
+            last_flash: None,
+            segments: Vec::new(),
+        }
+    }
+
+    fn set_max_flash_hz(&mut self, max_flash_hz: f64) {
+        self.min_interval = Duration::from_secs_f64(1.0 / max_flash_hz);
+    }
+
+    /// Dim the segments of `frame`, about to be sent at `now`, that would flash too soon after
+    /// the last flash
+    fn limit(&mut self, frame: &mut Array2<u8>, now: Instant) {
+        let n_segments = frame.nrows().div_ceil(FLASH_SEGMENT_PIXELS);
+        if self.segments.len() != n_segments {
+            self.segments = vec![Default::default(); n_segments];
+        }
+        let too_soon = self
+            .last_flash
+            .is_some_and(|last| now.duration_since(last) < self.min_interval);
+
+        let mut flashed = false;
+        for (segment, measures) in self.segments.iter_mut().enumerate() {
+            let start = segment * FLASH_SEGMENT_PIXELS;
+            let end = (start + FLASH_SEGMENT_PIXELS).min(frame.nrows());
+            let mut pixels = frame.slice_mut(s![start..end, ..]);
+            let mut levels = light(&pixels.view());
+            let rises = measures
+                .iter()
+                .zip(levels)
+                .filter_map(|(transitions, level)| {
+                    transitions
+                        .limit()
+                        .filter(|limit| level >= *limit)
+                        .map(|limit| (level, limit))
+                });
+            if too_soon {
+                // hold the rise just short of a flash, both measures scale with the pixels
+                let scale = rises.fold(1.0, |scale: f64, (level, limit)| {
+                    scale.min((limit - FLASH_THRESHOLD * 0.01) / level)
+                });
+                if scale < 1.0 {
+                    pixels.mapv_inplace(|x| (x as f64 * scale) as u8);
+                    levels = light(&pixels.view());
+                }
+            } else if rises.count() > 0 {
+                flashed = true;
+            }
+            for (transitions, level) in measures.iter_mut().zip(levels) {
+                transitions.update(level);
+            }
+        }
+        if flashed {
+            self.last_flash = Some(now);
+        }
+    }
+}
+
+/// Average luminance and saturated red of `pixels`, from 0 to 1
+fn light(pixels: &ArrayView2<u8>) -> [f64; 2] {
+    let mut total = [0.0; 2];
+    for rgb in pixels.rows() {
+        let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|x| x as f64 / 255.0);
+        total[0] += 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        // red with neither of the other colors mixed in
+        total[1] += (r - g.max(b)).max(0.0);
+    }
+    total.map(|x| x / pixels.nrows().max(1) as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay_line_holds_frames_back() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let frame = |value| Array2::from_elem((1, 3), value);
+        let mut frames = DelayLine::new(ms(50));
+        frames.push(frame(1), start);
+        frames.push(frame(2), start + ms(10));
+        frames.push(frame(3), start + ms(20));
+
+        assert_eq!(frames.take_due(start + ms(40)), None);
+        assert_eq!(frames.take_due(start + ms(50)), Some(frame(1)));
+        // a late tick sends only the newest of the frames it missed
+        assert_eq!(frames.take_due(start + ms(75)), Some(frame(3)));
+        assert_eq!(frames.take_due(start + ms(100)), None);
+
+        frames.delay = Duration::ZERO;
+        frames.push(frame(4), start + ms(100));
+        assert_eq!(frames.take_due(start + ms(100)), Some(frame(4)));
+    }
+
+    /// Alternate `on` and `off` every frame at `fps` for two seconds through a limiter of 2.5 Hz,
+    /// returning the frames where `on` got through undimmed
+    fn strobe(on: Array2<u8>, off: Array2<u8>, fps: u32) -> Vec<u32> {
+        let start = Instant::now();
+        let mut flashes = FlashLimiter::new(2.5);
+        let mut flashed = vec![];
+        for i in 0..2 * fps {
+            let mut frame = if i % 2 == 0 { on.clone() } else { off.clone() };
+            flashes.limit(&mut frame, start + Duration::from_secs(1) * i / fps);
+            if i % 2 == 0 && frame == on {
+                flashed.push(i);
+            }
+        }
+        flashed
+    }
+
+    #[test]
+    fn test_flashes_are_rate_limited() {
+        let fps = 60;
+        let rgb = |n_pixels, color: [u8; 3]| {
+            Array2::from_shape_fn((n_pixels, 3), |(_, channel)| color[channel])
+        };
+
+        // the whole strip strobing white
+        let whole = strobe(rgb(10, [255; 3]), rgb(10, [0; 3]), fps);
+        // a block of a longer strip strobing white
+        let mut block = rgb(60, [0; 3]);
+        block.slice_mut(s![20..30, ..]).fill(255);
+        let partial = strobe(block, rgb(60, [0; 3]), fps);
+        // red and blue swapping
+        let red = strobe(rgb(10, [255, 0, 0]), rgb(10, [0, 0, 255]), fps);
+        for flashed in [whole, partial, red] {
+            assert_eq!(flashed.len(), 5, "flashes at {flashed:?}");
+            assert!(flashed
+                .windows(2)
+                .all(|pair| pair[1] - pair[0] >= fps * 2 / 5));
+        }
+    }
+
+    #[test]
+    fn test_fades_are_not_limited() {
+        let start = Instant::now();
+        let frame_time = |i: u32| start + Duration::from_secs(1) * i / 60;
+        // fading up slowly is never held back
+        let mut flashes = FlashLimiter::new(2.5);
+        for i in 0..=255 {
+            let mut frame = Array2::from_elem((10, 3), i as u8);
+            flashes.limit(&mut frame, frame_time(i));
+            assert_eq!(frame[[0, 0]], i as u8);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use cpal::{traits::StreamTrait, Stream};
+
+use crate::{
+    audio::new_playback_stream,
+    config::{Config, MAX_LATENCY_OFFSET_MS},
+    ring_buffer::Consumer,
+};
+
+/// How far past its delay the playback may run before the oldest audio is dropped to catch up,
+/// so that the capture and playback callbacks can take turns unevenly without any audio lost
+const PLAYBACK_SLACK: Duration = Duration::from_millis(20);
+
+/// Play the audio arriving on `samples` on the default output device, `delay` frames after it
+/// was captured, for sources that are captured from a loopback device rather than heard
+/// directly. Holding the sound back is how a negative latency offset lets the lights catch up.
+/// Playback stops once the returned stream is dropped.
+pub fn start_playback(config: &Config, mut samples: Consumer, delay: Arc<AtomicUsize>) -> Stream {
+    let rate = config.mic_rate as f64;
+    let mut line = AudioDelay::new(
+        config.channels as usize,
+        (MAX_LATENCY_OFFSET_MS as f64 / 1000. * rate) as usize,
+        (PLAYBACK_SLACK.as_secs_f64() * rate) as usize,
+    );
+    let mut incoming = vec![0.0; 4096 * config.channels as usize];
+    let stream = new_playback_stream(config, move |out, out_channels| {
+        loop {
+            let n = samples.pop_slice(&mut incoming);
+            if n == 0 {
+                break;
+            }
+            line.push(&incoming[..n]);
+        }
+        line.fill(out, out_channels, delay.load(Ordering::Relaxed));
+    });
+    stream.play().expect("error playing audio output stream");
+    stream
+}
+
+/// Number of frames at the config's sample rate that a negative latency offset holds the audio
+/// back by, zero for offsets that delay the lights instead
+pub fn playback_delay_frames(config: &Config) -> usize {
+    let delay_ms = (-config.latency_offset_ms).max(0) as u64;
+    (delay_ms * config.mic_rate as u64 / 1000) as usize
+}
+
+/// Holds captured audio back by a number of frames on its way to the output
+struct AudioDelay {
+    channels: usize,
+    slack: usize,
+    // interleaved samples waiting to be played, oldest first
+    queue: VecDeque<f32>,
+}
+
+impl AudioDelay {
+    fn new(channels: usize, max_delay: usize, slack: usize) -> Self {
+        Self {
+            channels,
+            slack,
+            queue: VecDeque::with_capacity((max_delay + 2 * slack) * channels),
+        }
+    }
+
+    /// Queue newly captured samples, interleaved with this delay's channel count
+    fn push(&mut self, samples: &[f32]) {
+        self.queue.extend(samples);
+    }
+
+    /// Fill `out`, interleaved with `out_channels`, with the queued audio from `delay` frames
+    /// ago. The queue is padded with silence when it runs short, e.g. at the start or when the
+    /// delay grows, and its oldest frames are dropped when it runs long.
+    fn fill(&mut self, out: &mut [f32], out_channels: usize, delay: usize) {
+        let n_frames = out.len() / out_channels;
+        let wanted = delay.max(n_frames);
+        let queued = self.queue.len() / self.channels;
+        if queued < wanted {
+            for _ in 0..(wanted - queued) * self.channels {
+                self.queue.push_front(0.0);
+            }
+        } else if queued > wanted + self.slack {
+            self.queue.drain(..(queued - wanted) * self.channels);
+        }
+
+        for frame in out.chunks_mut(out_channels) {
+            let samples = self.queue.drain(..self.channels);
+            if out_channels == self.channels {
+                for (out, sample) in frame.iter_mut().zip(samples) {
+                    *out = sample;
+                }
+            } else {
+                let mix = samples.sum::<f32>() / self.channels as f32;
+                frame.fill(mix);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_audio_delay_holds_audio_back() {
+        let mut line = AudioDelay::new(1, 10, 2);
+        let mut out = [0.0; 2];
+        // each frame plays three frames after it was captured, with silence until the first
+        line.push(&[1.0, 2.0]);
+        line.fill(&mut out, 1, 3);
+        assert_eq!(out, [0.0, 1.0]);
+        line.push(&[3.0, 4.0]);
+        line.fill(&mut out, 1, 3);
+        assert_eq!(out, [2.0, 3.0]);
+
+        // capture running ahead past the slack is dropped to get back to the delay
+        line.push(&[5.0, 6.0, 7.0, 8.0, 9.0, 10.0]);
+        line.fill(&mut out, 1, 3);
+        assert_eq!(out, [8.0, 9.0]);
+
+        // a longer delay pads the queue with silence
+        line.push(&[11.0, 12.0]);
+        line.fill(&mut out, 1, 6);
+        assert_eq!(out, [0.0, 0.0]);
+        line.push(&[13.0, 14.0]);
+        line.fill(&mut out, 1, 6);
+        assert_eq!(out, [0.0, 10.0]);
+    }
+
+    #[test]
+    fn test_audio_delay_maps_channels() {
+        let mut out = [0.0; 4];
+        let mut mono = AudioDelay::new(1, 0, 0);
+        mono.push(&[0.5, -0.5]);
+        mono.fill(&mut out, 2, 0);
+        assert_eq!(out, [0.5, 0.5, -0.5, -0.5]);
+
+        let mut stereo = AudioDelay::new(2, 0, 0);
+        stereo.push(&[0.25, 0.75, 0.5, 1.0]);
+        stereo.fill(&mut out, 2, 0);
+        assert_eq!(out, [0.25, 0.75, 0.5, 1.0]);
+        stereo.push(&[0.25, 0.75, 0.5, 1.0]);
+        stereo.fill(&mut out[..2], 1, 0);
+        assert_eq!(out[..2], [0.5, 0.75]);
+    }
+
+    #[test]
+    fn test_playback_delay_frames() {
+        let config = |latency_offset_ms| Config {
+            latency_offset_ms,
+            mic_rate: 48000,
+            ..Default::default()
+        };
+        assert_eq!(playback_delay_frames(&config(-100)), 4800);
+        assert_eq!(playback_delay_frames(&config(0)), 0);
+        assert_eq!(playback_delay_frames(&config(100)), 0);
+    }
+}
@@ -1,147 +1,552 @@
 use std::{
-    sync, thread,
+    sync::{
+        self,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
     time::{Duration, Instant},
 };
 
-use crate::gui::waveform::pipeline::Vertex;
-use cpal::{traits::StreamTrait, InputCallbackInfo};
-use iced::futures::channel::mpsc::{Receiver, Sender};
-use ndarray::{arr1, concatenate, s, Array1, Array2, Axis};
+use cpal::{traits::StreamTrait, InputCallbackInfo, Stream};
+use ndarray::Array2;
 
 use crate::{
     audio::new_audio_stream,
     config::Config,
-    dsp::{self, Dsp},
-    gui::GuiMessage,
+    dsp::{self, history::History, Analysis, Dsp, Effect, InputFilter},
     led::ESP8266Conn,
+    output::Output,
+    playback::{playback_delay_frames, start_playback},
+    ring_buffer::{ring_buffer, Consumer},
 };
 
+/// How often the headless loop reports on the renderer's progress
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+/// How much audio the ring buffer between the audio callback and the analysis thread holds
+/// before samples are dropped
+const CAPTURE_BUFFER_TIME: Duration = Duration::from_millis(500);
+/// Longest the analysis thread sleeps between checks for new samples, in case a wakeup from the
+/// audio callback is missed
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
 pub struct Renderer {
     display_values: Array2<f64>,
     send_buffer: Array2<u8>,
-    selected_preset: dsp::Preset,
-    rolling_history: Array1<f64>,
-    last_render: Instant,
-    update_tx: Option<Sender<GuiMessage>>,
-    frame_duration: Duration,
+    effect: Box<dyn Effect>,
+    rolling_history: History,
+    // the left and right channels, for stereo input only, alongside their mix in rolling_history
+    channel_histories: Option<[History; 2]>,
+    hop: Hop,
     config: Config,
-    conn: ESP8266Conn,
+    output: Output,
     dsp: Dsp,
-    ready: bool,
+    stats: Arc<RenderStats>,
+    config_updates: Option<sync::mpsc::Receiver<Config>>,
+    // gain settings from the gui, which take precedence over the config file's
+    gain_updates: Option<sync::mpsc::Receiver<GainOverride>>,
+    gain_override: Option<GainOverride>,
+    // frames that the captured audio is held back by on its way to the output device, for
+    // playback only
+    playback_delay: Option<Arc<AtomicUsize>>,
 }
 
-impl Renderer {
-    pub fn new(config: Config, update_tx: Option<Sender<GuiMessage>>) -> Self {
-        let frame_duration = Duration::from_secs_f64(1. / config.fps as f64);
+/// The audio streams of a started renderer, which stop capturing, and playing back, once this
+/// is dropped
+pub struct RendererStreams {
+    _capture: Stream,
+    _playback: Option<Stream>,
+}
+
+/// Gain settings chosen in the gui
+#[derive(Debug, Clone, Copy)]
+pub struct GainOverride {
+    pub mode: dsp::GainMode,
+    pub manual_gain_db: f64,
+}
+
+/// Counters written from the audio, analysis and output threads and read by whoever is
+/// supervising the renderer
+#[derive(Default)]
+pub struct RenderStats {
+    frames: AtomicU64,
+    beats: AtomicU64,
+    // f64 bits of the rms level of the rolling history at the last rendered frame
+    rms: AtomicU64,
+    // f64 bits of the estimated tempo, zero until one is found
+    bpm: AtomicU64,
+    // samples the audio callback dropped because the analysis thread fell behind
+    dropped_samples: AtomicU64,
+    // output timer ticks that found no new frame to send
+    late_frames: AtomicU64,
+}
+
+impl RenderStats {
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    pub fn beats(&self) -> u64 {
+        self.beats.load(Ordering::Relaxed)
+    }
+
+    pub fn rms(&self) -> f64 {
+        f64::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    pub fn bpm(&self) -> Option<f64> {
+        Some(f64::from_bits(self.bpm.load(Ordering::Relaxed))).filter(|bpm| *bpm > 0.0)
+    }
+
+    pub fn dropped_samples(&self) -> u64 {
+        self.dropped_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn late_frames(&self) -> u64 {
+        self.late_frames.load(Ordering::Relaxed)
+    }
+}
+
+impl RenderStats {
+    fn record_frame(&self, rms: f64, analysis: &Analysis) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.beats
+            .fetch_add(analysis.onset.beat as u64, Ordering::Relaxed);
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+        self.bpm.store(
+            analysis.tempo.bpm.unwrap_or(0.0).to_bits(),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn record_late_frame(&self) {
+        self.late_frames.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts captured samples into hops of a fixed length, so that a frame is analyzed for every
+/// hop of audio however many samples each callback delivers
+struct Hop {
+    len: usize,
+    filled: usize,
+}
 
+impl Hop {
+    fn new(config: &Config) -> Self {
+        Self {
+            len: hop_len(config),
+            filled: 0,
+        }
+    }
+
+    /// Take up to `available` samples, returning how many belong to the current hop and whether
+    /// they complete it
+    fn advance(&mut self, available: usize) -> (usize, bool) {
+        let taken = available.min(self.len - self.filled);
+        self.filled += taken;
+        if self.filled == self.len {
+            self.filled = 0;
+            (taken, true)
+        } else {
+            (taken, false)
+        }
+    }
+
+    /// Change the hop length, ending the current hop early if it's already past the new length
+    fn resize(&mut self, len: usize) {
+        self.len = len.max(1);
+        self.filled = self.filled.min(self.len - 1);
+    }
+}
+
+/// Samples of audio per frame, at least one even if a config asks for more frames than samples
+fn hop_len(config: &Config) -> usize {
+    (config.mic_rate / config.fps).max(1) as usize
+}
+
+/// An empty history of the input audio, filtered as the config says
+fn input_history(config: &Config) -> History {
+    let mut history = History::new(config.n_fft_bins as usize);
+    history.set_filter(InputFilter::new(config));
+    history
+}
+
+fn frame_duration(config: &Config) -> Duration {
+    Duration::from_secs_f64(1. / config.fps as f64)
+}
+
+/// How long a positive latency offset holds the led frames back, zero for negative offsets,
+/// which hold back the audio instead
+fn latency_offset(config: &Config) -> Duration {
+    Duration::from_millis(config.latency_offset_ms.max(0) as u64)
+}
+
+impl Renderer {
+    pub fn new(config: Config) -> Self {
+        let stats = Arc::new(RenderStats::default());
         Self {
             display_values: Array2::<f64>::zeros((config.n_points as usize, 3)),
             send_buffer: Array2::<u8>::zeros((config.n_points as usize, 3)),
-            selected_preset: dsp::Preset::Scroll,
-            rolling_history: Array1::<f64>::zeros(config.n_fft_bins as usize),
-            frame_duration,
-            update_tx,
-            last_render: Instant::now() - frame_duration, // start rendering on our first sample
+            effect: config.preset.effect(&config),
+            rolling_history: input_history(&config),
+            channel_histories: (config.channels == 2)
+                .then(|| [0, 1].map(|_| input_history(&config))),
+            hop: Hop::new(&config),
             config: config.clone(),
-            conn: ESP8266Conn::new(&config).unwrap(),
+            output: Output::spawn(
+                ESP8266Conn::new(&config).unwrap(),
+                frame_duration(&config),
+                latency_offset(&config),
+                config.max_flash_hz,
+                stats.clone(),
+            ),
             dsp: Dsp::new(config),
-            ready: false,
+            stats,
+            config_updates: None,
+            gain_updates: None,
+            gain_override: None,
+            playback_delay: None,
         }
     }
 
-    fn apply_updates(&mut self, u: GuiMessage) {}
+    /// Apply configs received on `updates` to the running renderer, e.g. from
+    /// [`crate::config::watch_config`]
+    pub fn with_config_updates(mut self, updates: sync::mpsc::Receiver<Config>) -> Self {
+        self.config_updates = Some(updates);
+        self
+    }
 
-    pub fn main_loop(
-        mut self,
-        stop: sync::mpsc::Receiver<()>,
-        in_channel: Receiver<GuiMessage>,
-        out_channel: Sender<Array2<u8>>,
-    ) {
-        let stream = new_audio_stream(
-            self.config.clone(),
-            move |audio_data: &[f32], _: &InputCallbackInfo| {
-                self.update(audio_data);
-            },
-        );
-        stream.play().expect("error playing audio stream");
-        stop.recv().expect("error accepting thread stop signal");
-    }
-
-    fn update(&mut self, audio_data: &[f32]) {
-        // move in new audio samples to buffer (back is newest)
-        let new_data = arr1(audio_data).mapv(f64::from);
-        self.rolling_history = concatenate![
-            Axis(0),
-            self.rolling_history.slice(s![new_data.shape()[0]..]),
-            new_data
-        ];
-
-        // re-render when we encounter a frame boundary
-        if self.last_render.elapsed() > self.frame_duration {
-            self.last_render = Instant::now();
-
-            // transform the audio to the frequency space and then to the mel spectrum
-            let audio_data_rfft = self.dsp.exec_rfft(&self.rolling_history);
-            let mut audio_data_mel = self.dsp.get_mel_repr(&audio_data_rfft);
-            self.dsp.gain_and_smooth(&mut audio_data_mel);
-
-            self.dsp
-                .apply_transform_inplace(self.selected_preset.clone(), &mut self.display_values);
-
-            let mut new_send_buffer: Array2<u8> = self.display_values.map(|v| {
-                if *v < 0.0 {
-                    0
-                } else if *v > 255.0 {
-                    255
-                } else {
-                    *v as u8
+    /// Layer gain settings received on `updates` over the config, including over configs that
+    /// arrive later from [`Self::with_config_updates`]
+    pub fn with_gain_updates(mut self, updates: sync::mpsc::Receiver<GainOverride>) -> Self {
+        self.gain_updates = Some(updates);
+        self
+    }
+
+    /// The config currently rendered with, after any updates and gain settings are applied
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The last frame sent to the strip
+    pub fn send_buffer(&self) -> &Array2<u8> {
+        &self.send_buffer
+    }
+
+    /// The analysis of the audio behind the last frame
+    pub fn analysis(&self) -> &Analysis {
+        self.dsp.analysis()
+    }
+
+    /// Counters of the renderer's progress, which keep updating once it has started
+    pub fn stats(&self) -> Arc<RenderStats> {
+        self.stats.clone()
+    }
+
+    fn check_config_updates(&mut self) {
+        // only the newest pending revision matters
+        let config = self
+            .config_updates
+            .as_ref()
+            .and_then(|updates| updates.try_iter().last());
+        let config = self.with_gain_override(config);
+        let Some(config) = config else {
+            return;
+        };
+        self.apply_config(config);
+    }
+
+    /// Layer the gain settings from the gui over `config`, or over the current config if only
+    /// the gain settings changed
+    fn with_gain_override(&mut self, mut config: Option<Config>) -> Option<Config> {
+        if let Some(gain) = self
+            .gain_updates
+            .as_ref()
+            .and_then(|updates| updates.try_iter().last())
+        {
+            self.gain_override = Some(gain);
+            config.get_or_insert_with(|| self.config.clone());
+        }
+        let mut config = config?;
+        if let Some(gain) = self.gain_override {
+            config.gain_mode = gain.mode;
+            config.manual_gain_db = gain.manual_gain_db;
+        }
+        Some(config)
+    }
+
+    fn apply_config(&mut self, mut config: Config) {
+        if config.mic_rate != self.config.mic_rate {
+            println!("mic_rate changes take effect after a restart");
+            config.mic_rate = self.config.mic_rate;
+        }
+        if config.channels != self.config.channels {
+            println!("channels changes take effect after a restart");
+            config.channels = self.config.channels;
+        }
+        if config.playback != self.config.playback {
+            println!("playback changes take effect after a restart");
+            config.playback = self.config.playback;
+        }
+        if config == self.config {
+            return;
+        }
+
+        if config.output_changed(&self.config) {
+            match ESP8266Conn::new(&config) {
+                Ok(conn) => self.output.set_connection(conn),
+                Err(err) => {
+                    println!(
+                        "Could not open the new led connection, keeping the current one: {err}"
+                    );
+                    config.device_ip = self.config.device_ip.clone();
+                    config.device_port = self.config.device_port;
+                    config.software_gamma_correction = self.config.software_gamma_correction;
                 }
-            });
+            }
+        }
+        if config.n_points != self.config.n_points {
+            self.display_values = Array2::zeros((config.n_points as usize, 3));
+            self.send_buffer = Array2::zeros((config.n_points as usize, 3));
+        }
+        if config.n_fft_bins != self.config.n_fft_bins {
+            self.rolling_history.resize(config.n_fft_bins as usize);
+            for history in self.channel_histories.iter_mut().flatten() {
+                history.resize(config.n_fft_bins as usize);
+            }
+        }
+        if config.input_filter_changed(&self.config) {
+            self.rolling_history.set_filter(InputFilter::new(&config));
+            for history in self.channel_histories.iter_mut().flatten() {
+                history.set_filter(InputFilter::new(&config));
+            }
+        }
+        if config.fps != self.config.fps {
+            self.hop.resize(hop_len(&config));
+            self.output.set_frame_duration(frame_duration(&config));
+        }
+        if config.latency_offset_ms != self.config.latency_offset_ms {
+            if let Some(delay) = &self.playback_delay {
+                delay.store(playback_delay_frames(&config), Ordering::Relaxed);
+            }
+            self.output.set_delay(latency_offset(&config));
+        }
+        if config.max_flash_hz != self.config.max_flash_hz {
+            self.output.set_max_flash_hz(config.max_flash_hz);
+        }
+        if config.preset != self.config.preset {
+            self.effect = config.preset.effect(&config);
+        } else {
+            self.effect.update_config(&config);
+        }
+        self.dsp.update_config(config.clone());
+        self.config = config;
+    }
 
-            self.conn
-                .update(&mut new_send_buffer, &self.send_buffer)
-                .expect("error updating connection");
+    /// Run the renderer without a GUI, printing a status line every few seconds until a stop
+    /// signal is received
+    pub fn main_loop(self, stop: sync::mpsc::Receiver<()>) {
+        let stats = self.stats.clone();
+        let _stream = self.start(|_| {});
 
-            self.send_buffer = new_send_buffer;
+        let mut last_status = Instant::now();
+        let mut last_frames = 0;
+        // a stop signal or a dropped sender both end the loop
+        while let Err(sync::mpsc::RecvTimeoutError::Timeout) = stop.recv_timeout(STATUS_INTERVAL) {
+            let frames = stats.frames();
+            let fps = (frames - last_frames) as f64 / last_status.elapsed().as_secs_f64();
+            let tempo = match stats.bpm() {
+                Some(bpm) => format!("{:.0} bpm", bpm),
+                None => String::from("no tempo yet"),
+            };
+            println!(
+                "rendered {} frames ({:.1} fps, {} late), input rms {:.2e} ({} samples dropped), \
+                 {} beats, {}",
+                frames,
+                fps,
+                stats.late_frames(),
+                stats.rms(),
+                stats.dropped_samples(),
+                stats.beats(),
+                tempo
+            );
+            last_status = Instant::now();
+            last_frames = frames;
         }
     }
 
-    pub fn main_loop_external_updates(mut self) {
-        let (stop_tx, stop_rx) = sync::mpsc::channel::<()>();
-        self.update_tx
-            .as_mut()
-            .expect("update tx should exist in main loop setup")
-            .try_send(GuiMessage::StopTx(stop_tx))
-            .expect("update tx should be ready to accept messages");
+    /// Capture audio into a ring buffer from the audio callback and analyze and render it on a
+    /// thread of its own, so the callback never waits on the dsp or the network. `on_frames` is
+    /// called on the analysis thread whenever new frames have been rendered. With playback on, the
+    /// captured audio is also played on the default output device, held back by a negative
+    /// latency offset. Capture, playback and analysis stop once the returned streams are dropped.
+    pub fn start(
+        mut self,
+        mut on_frames: impl FnMut(&Renderer) + Send + 'static,
+    ) -> RendererStreams {
+        let channels = self.config.channels as usize;
+        let capacity =
+            (CAPTURE_BUFFER_TIME.as_secs_f64() * self.config.mic_rate as f64) as usize * channels;
+        let (mut producer, mut consumer) = ring_buffer(capacity);
+        let stats = self.stats.clone();
+        let config = self.config.clone();
+
+        let (mut playback_producer, playback) = if config.playback {
+            let (producer, consumer) = ring_buffer(capacity);
+            let delay = Arc::new(AtomicUsize::new(playback_delay_frames(&config)));
+            self.playback_delay = Some(delay.clone());
+            (
+                Some(producer),
+                Some(start_playback(&config, consumer, delay)),
+            )
+        } else {
+            (None, None)
+        };
 
-        let stream = new_audio_stream(
-            self.config.clone(),
-            move |audio_data: &[f32], _: &InputCallbackInfo| {
-                if !self.ready {
-                    // do thread communication init here
-                    self.ready = true;
+        let analysis_thread = thread::spawn(move || {
+            // whole frames of every channel at a time, as the callback only writes whole frames
+            let mut samples = vec![0.0; 1024 * channels];
+            while let Some(n) = next_samples(&mut consumer, &mut samples) {
+                if self.update(&samples[..n]) > 0 {
+                    on_frames(&self);
                 }
-                self.update(audio_data);
-
-                self.update_tx
-                    .as_mut()
-                    .expect("update tx should exist in render thread")
-                    .try_send(GuiMessage::PointsUpdated(send_buffer_to_vertex(
-                        &self.send_buffer,
-                    )))
-                    .expect("send points update should succeed if channel is open");
-            },
-        );
-        stream.play().expect("audio stream should be ready to play");
-        stop_rx
-            .recv()
-            .expect("stop receiver exists and should not have been closed");
+            }
+        });
+        let analysis_thread = analysis_thread.thread().clone();
+
+        let stream = new_audio_stream(config, move |audio_data: &[f32], _: &InputCallbackInfo| {
+            // drop whole frames of every channel so the channels stay interleaved in order
+            let fits = producer.free() / channels * channels;
+            let written = producer.push_slice(&audio_data[..audio_data.len().min(fits)]);
+            if written < audio_data.len() {
+                stats
+                    .dropped_samples
+                    .fetch_add((audio_data.len() - written) as u64, Ordering::Relaxed);
+            }
+            if let Some(playback) = &mut playback_producer {
+                let fits = playback.free() / channels * channels;
+                playback.push_slice(&audio_data[..audio_data.len().min(fits)]);
+            }
+            analysis_thread.unpark();
+        });
+        stream.play().expect("error playing audio stream");
+        RendererStreams {
+            _capture: stream,
+            _playback: playback,
+        }
+    }
+
+    /// Take in newly captured audio, rendering a frame for every hop of it that completes, and
+    /// return how many frames were rendered
+    fn update(&mut self, audio_data: &[f32]) -> usize {
+        self.check_config_updates();
+
+        let channels = self.config.channels as usize;
+        let mut frames = 0;
+        let mut rest = audio_data;
+        while !rest.is_empty() {
+            let (taken, hop_done) = self.hop.advance(rest.len() / channels);
+            let (samples, remaining) = rest.split_at(taken * channels);
+            self.rolling_history.push_downmix(samples, channels);
+            if let Some(histories) = &mut self.channel_histories {
+                for (channel, history) in histories.iter_mut().enumerate() {
+                    history.push_channel(samples, channels, channel);
+                }
+            }
+            if hop_done {
+                self.render_frame();
+                frames += 1;
+            }
+            rest = remaining;
+        }
+        frames
+    }
+
+    fn render_frame(&mut self) {
+        // transform the audio to the mel spectrum and look for onsets
+        self.dsp.analyze(&self.rolling_history);
+        if let Some([left, right]) = &self.channel_histories {
+            self.dsp.analyze_stereo(left, right);
+        }
+
+        self.effect
+            .render(self.dsp.analysis(), &mut self.display_values);
+
+        // fade the output rather than the effect's own state, which some effects feed back
+        let gate = self.dsp.analysis().gate;
+        self.send_buffer = self
+            .display_values
+            .map(|v| (v * gate).clamp(0.0, 255.0) as u8);
+        self.output.send_frame(self.send_buffer.clone());
+
+        self.stats
+            .record_frame(self.rolling_history.rms(), self.dsp.analysis());
     }
 }
 
-fn send_buffer_to_vertex(send_buffer: &Array2<u8>) -> Vec<Vertex> {
-    send_buffer
-        .axis_iter(Axis(0))
-        .map(|col| Vertex([col[0] as i32, col[1] as i32, col[2] as i32]))
-        .collect::<Vec<Vertex>>()
+/// Wait for captured samples and read as many as fit into `samples`, or `None` once the audio
+/// stream is gone
+fn next_samples(consumer: &mut Consumer, samples: &mut [f32]) -> Option<usize> {
+    loop {
+        match consumer.pop_slice(samples) {
+            0 if consumer.is_abandoned() => return None,
+            0 => thread::park_timeout(CAPTURE_POLL_INTERVAL),
+            n => return Some(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray_rand::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_hop_splits_samples_at_hop_boundaries() {
+        let mut hop = Hop { len: 10, filled: 0 };
+        assert_eq!(hop.advance(4), (4, false));
+        assert_eq!(hop.advance(25), (6, true));
+        assert_eq!(hop.advance(19), (10, true));
+        assert_eq!(hop.advance(9), (9, false));
+        // shrinking below what's filled ends the hop with the next sample
+        hop.resize(5);
+        assert_eq!(hop.advance(3), (1, true));
+
+        // a hop is never shorter than a sample
+        hop.resize(0);
+        assert_eq!(hop.advance(3), (1, true));
+    }
+
+    #[test]
+    fn test_frames_follow_samples_not_callbacks() {
+        // the frames go to a socket of our own rather than to the default port, which other tests
+        // listen on
+        let strip = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let config = Config {
+            device_ip: String::from("127.0.0.1"),
+            device_port: strip.local_addr().unwrap().port().into(),
+            channels: 2,
+            ..Default::default()
+        };
+        let hop = (config.mic_rate / config.fps) as usize;
+        let mut renderer = Renderer::new(config.clone());
+        let mut rng = StdRng::seed_from_u64(40);
+
+        // two seconds of stereo audio in callbacks of every size from one sample to several hops
+        let n_samples = 2 * config.mic_rate as usize;
+        let audio: Vec<f32> = (0..2 * n_samples)
+            .map(|i| (i as f32 * 0.01).sin())
+            .collect();
+        let mut frames = 0;
+        let mut rest = &audio[..];
+        while !rest.is_empty() {
+            let len = rng.gen_range(1..=3 * hop).min(rest.len() / 2);
+            let (callback, remaining) = rest.split_at(2 * len);
+            frames += renderer.update(callback);
+            rest = remaining;
+        }
+        assert_eq!(frames, n_samples / hop);
+        assert_eq!(renderer.stats.frames.load(Ordering::Relaxed), frames as u64);
+    }
 }
@@ -0,0 +1,158 @@
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+struct Shared {
+    samples: Box<[UnsafeCell<f32>]>,
+    // total samples ever read and written, their difference is how many are waiting to be read
+    read: AtomicUsize,
+    written: AtomicUsize,
+}
+
+// the producer only touches slots that the consumer has released and vice versa, handing them
+// over through `read` and `written`
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn mask(&self) -> usize {
+        self.samples.len() - 1
+    }
+}
+
+/// Lock-free single producer, single consumer ring buffer of audio samples, so that the audio
+/// callback can hand samples to the analysis thread without locking or allocating. `capacity`
+/// is rounded up to a power of two.
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    let shared = Arc::new(Shared {
+        samples: (0..capacity.max(1).next_power_of_two())
+            .map(|_| UnsafeCell::new(0.0))
+            .collect(),
+        read: AtomicUsize::new(0),
+        written: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    )
+}
+
+/// The writing end of a [`ring_buffer`]
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+impl Producer {
+    /// How many more samples fit before the consumer catches up
+    pub fn free(&self) -> usize {
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let read = self.shared.read.load(Ordering::Acquire);
+        self.shared.samples.len() - written.wrapping_sub(read)
+    }
+
+    /// Write as many of `samples` as fit, returning how many were written
+    pub fn push_slice(&mut self, samples: &[f32]) -> usize {
+        let n = samples.len().min(self.free());
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let mask = self.shared.mask();
+        for (i, sample) in samples[..n].iter().enumerate() {
+            // SAFETY: the consumer doesn't read past `written` until it's published below
+            unsafe { *self.shared.samples[written.wrapping_add(i) & mask].get() = *sample };
+        }
+        self.shared
+            .written
+            .store(written.wrapping_add(n), Ordering::Release);
+        n
+    }
+}
+
+/// The reading end of a [`ring_buffer`]
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+impl Consumer {
+    /// Read as many waiting samples as fit in `samples`, oldest first, returning how many were
+    /// read
+    pub fn pop_slice(&mut self, samples: &mut [f32]) -> usize {
+        let read = self.shared.read.load(Ordering::Relaxed);
+        let written = self.shared.written.load(Ordering::Acquire);
+        let n = samples.len().min(written.wrapping_sub(read));
+        let mask = self.shared.mask();
+        for (i, sample) in samples[..n].iter_mut().enumerate() {
+            // SAFETY: the producer doesn't overwrite anything past `read` until it's published
+            // below
+            *sample = unsafe { *self.shared.samples[read.wrapping_add(i) & mask].get() };
+        }
+        self.shared
+            .read
+            .store(read.wrapping_add(n), Ordering::Release);
+        n
+    }
+
+    /// Whether the producer has been dropped, so no more samples will arrive
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_wraps_and_drops_what_doesnt_fit() {
+        let (mut producer, mut consumer) = ring_buffer(3);
+        let mut out = [0.0; 4];
+        assert_eq!(producer.free(), 4);
+        assert_eq!(producer.push_slice(&[1.0, 2.0, 3.0]), 3);
+        assert_eq!(consumer.pop_slice(&mut out[..2]), 2);
+        assert_eq!(out[..2], [1.0, 2.0]);
+
+        // only three of these fit behind the waiting sample
+        assert_eq!(producer.push_slice(&[4.0, 5.0, 6.0, 7.0]), 3);
+        assert_eq!(producer.free(), 0);
+        assert_eq!(consumer.pop_slice(&mut out), 4);
+        assert_eq!(out, [3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(consumer.pop_slice(&mut out), 0);
+
+        assert!(!consumer.is_abandoned());
+        drop(producer);
+        assert!(consumer.is_abandoned());
+    }
+
+    #[test]
+    fn test_ring_buffer_across_threads() {
+        let (mut producer, mut consumer) = ring_buffer(64);
+        let n_samples = 20_000;
+        let writer = thread::spawn(move || {
+            let mut next = 0;
+            while next < n_samples {
+                let chunk: Vec<f32> = (next..(next + 37).min(n_samples))
+                    .map(|i| i as f32)
+                    .collect();
+                next += producer.push_slice(&chunk);
+                thread::yield_now();
+            }
+        });
+
+        let mut expected = 0;
+        let mut out = [0.0; 50];
+        while expected < n_samples {
+            let n = consumer.pop_slice(&mut out);
+            thread::yield_now();
+            for sample in &out[..n] {
+                assert_eq!(*sample, expected as f32);
+                expected += 1;
+            }
+        }
+        writer.join().unwrap();
+    }
+}